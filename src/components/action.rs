@@ -0,0 +1,75 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! What actors do with their turns.
+//!
+//! The player's input and the AI both come down to an `Action`, which
+//! the `ActionSystem` checks and carries out the same way whoever is
+//! taking it.
+
+use super::player::{ItemSlot, Reload};
+use super::position::Position;
+use ::util::Direction;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Action {
+    /// Step to a neighbouring cell, picking up anything lying there.
+    /// Stepping into a door opens it, or kicks at it if it's locked.
+    Move(Direction),
+    /// Attack whatever is at `target` with the primary weapon, or the
+    /// secondary one for melee.
+    Attack { target: Position, melee: bool },
+    /// Pick up the item in a neighbouring cell without moving.
+    PickUp(Direction),
+    /// Equip the item at this index of the inventory.
+    Equip(usize),
+    Unequip(ItemSlot),
+    /// Use the consumable at this index of the inventory, throwing it
+    /// at `target` if it's thrown.
+    Use { index: usize, target: Option<Position> },
+    Reload,
+    /// Crouch down, or stand up again.
+    Crouch,
+    /// Start or stop sneaking. Takes effect from the next step, so it
+    /// costs nothing.
+    Sneak,
+    Wait,
+}
+
+/// Why an action can't be taken. Nothing happens, and no time passes.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum ActionError {
+    /// Something's in the way.
+    Blocked,
+    /// There's nothing there to pick up.
+    NothingThere,
+    /// The actor can't hold items.
+    NoInventory,
+    /// There's no item at that index of the inventory.
+    NoSuchItem,
+    CannotEquip,
+    /// The item isn't a consumable, or needs a target.
+    CannotUse,
+    FullHealth,
+    NothingEquipped,
+    OutOfAmmo,
+    OutOfRange,
+    /// Aimed at the actor's own cell.
+    NoTarget,
+    CannotReload(Reload),
+}
+
+/// The energy an action cost, or why it couldn't be taken.
+pub type ActionResult = Result<usize, ActionError>;
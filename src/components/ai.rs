@@ -16,9 +16,138 @@
 use specs;
 
 /// This entity chases the player if spotted.
-#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+#[derive(Clone,Debug,Default,Eq,PartialEq,Serialize,Deserialize)]
 pub struct ChaseBehavior {
     pub spotted: Option<(usize, usize)>,
+    /// The planned route to `spotted`, stored in reverse so that the
+    /// next step is at the end.
+    pub path: Vec<(usize, usize)>,
+    /// The map revision the route was planned on.
+    pub revision: u64,
+}
+
+/// How far away, in cells, allies hear a call for help.
+pub const HELP_RADIUS: usize = 8;
+/// How far from home, in cells, patrols wander.
+pub const PATROL_RADIUS: usize = 6;
+/// How far away, in cells, AIs look for cover.
+pub const COVER_SEARCH_RADIUS: usize = 4;
+
+/// Awareness at which an entity is sure it has seen its target.
+pub const AWARENESS_MAX: usize = 100;
+/// Awareness lost each turn the target isn't in sight.
+pub const AWARENESS_DECAY: usize = 10;
+/// How many turns an entity spends searching where it lost its
+/// target.
+pub const SEARCH_TURNS: usize = 10;
+/// How far from where the target was last seen, in cells, searches
+/// look.
+pub const SEARCH_RADIUS: usize = 3;
+
+/// What an AI-controlled entity can see, and what it makes of it.
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct Perception {
+    /// How far it can see, in cells.
+    pub sight: usize,
+    /// How far either side of its facing it can see, in degrees.
+    pub view_angle: u32,
+    /// The direction it's looking in, as a step.
+    pub facing: (i32, i32),
+    /// How sure it is that it has seen its target, up to
+    /// `AWARENESS_MAX`.
+    pub awareness: usize,
+    pub state: Awareness,
+    /// Where it last saw or heard its target.
+    pub last_seen: Option<(usize, usize)>,
+    /// Turns left searching around `last_seen`.
+    pub search_turns: usize,
+}
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum Awareness {
+    /// Hasn't noticed anything.
+    Unaware,
+    /// Has glimpsed something, and is watching.
+    Suspicious,
+    /// Knows where its target is, or was.
+    Alert,
+    /// Lost track of its target, and is looking around where it was
+    /// last seen.
+    Searching,
+}
+
+/// How an AI-controlled entity decides what to do, and where it
+/// calls home.
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct Behavior {
+    pub archetype: Archetype,
+    /// Where it patrols around, or guards.
+    pub home: (usize, usize),
+    /// Where it's patrolling to.
+    pub goal: Option<(usize, usize)>,
+    /// Whether it has already called for help since it spotted its
+    /// target.
+    pub called: bool,
+}
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum Archetype {
+    /// Patrols, calls for help, fights at any range and runs when
+    /// badly hurt.
+    Grunt,
+    /// Guards its post from behind cover, keeping its distance.
+    Sniper,
+    /// Patrols and closes in to fight up close, never running.
+    Brawler,
+}
+
+/// A behavior tree node. Running a node either fails, succeeds, or
+/// takes an action, which ends the turn.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Node {
+    /// Run each child in turn until one doesn't fail.
+    Selector(Vec<Node>),
+    /// Run each child in turn until one doesn't succeed.
+    Sequence(Vec<Node>),
+    Condition(Condition),
+    Task(Task),
+}
+
+/// A node that checks something, without acting.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Condition {
+    /// Health is below this percentage of the maximum.
+    HealthBelow(usize),
+    /// The target is in sight.
+    SeesTarget,
+    /// It knows (or remembers) where the target is.
+    Alerted,
+}
+
+/// A node that acts, if it can.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Task {
+    /// Attack the target, if it's in reach of a weapon.
+    Attack,
+    /// Reload an empty weapon.
+    Reload,
+    /// Move towards where the target was last seen.
+    Chase,
+    /// Move away from the target.
+    Flee,
+    /// Move to somewhere with cover from the target, unless already
+    /// there.
+    SeekCover,
+    /// Back away if the target is closer than this many steps.
+    KeepRange(usize),
+    /// Alert nearby allies to the target, once per sighting.
+    CallForHelp,
+    /// Wander between points around home.
+    Patrol,
+    /// Return home and wait there.
+    Guard,
+    /// Look around where the target was last seen, while searching.
+    Search,
 }
 
 /// This entity is dead and should not be processed by AI.
@@ -29,8 +158,151 @@ impl ChaseBehavior {
     pub fn new() -> ChaseBehavior {
         ChaseBehavior {
             spotted: None,
+            path: Vec::new(),
+            revision: 0,
+        }
+    }
+
+    /// Chase towards the given position, dropping the planned route
+    /// if the target has moved.
+    pub fn spot(&mut self, target: (usize, usize)) {
+        if self.spotted != Some(target) {
+            self.path.clear();
+        }
+        self.spotted = Some(target);
+    }
+
+    pub fn forget(&mut self) {
+        self.spotted = None;
+        self.path.clear();
+    }
+
+    /// The next step on the planned route, if any.
+    pub fn next_step(&self) -> Option<(usize, usize)> {
+        self.path.last().cloned()
+    }
+}
+
+impl Behavior {
+    pub fn new(archetype: Archetype, home: (usize, usize)) -> Behavior {
+        Behavior {
+            archetype: archetype,
+            home: home,
+            goal: None,
+            called: false,
+        }
+    }
+}
+
+impl Perception {
+    pub fn new(sight: usize, view_angle: u32) -> Perception {
+        Perception {
+            sight: sight,
+            view_angle: view_angle,
+            facing: (0, 1),
+            awareness: 0,
+            state: Awareness::Unaware,
+            last_seen: None,
+            search_turns: 0,
+        }
+    }
+
+    /// Whether `target` is within sight radius and inside the view
+    /// cone of something at `origin`. Anything adjacent is noticed
+    /// whichever way it's facing. Doesn't check line of sight.
+    pub fn in_view(&self, origin: (usize, usize), target: (usize, usize)) -> bool {
+        let offset = (target.0 as i32 - origin.0 as i32, target.1 as i32 - origin.1 as i32);
+        let distance2 = (offset.0 * offset.0 + offset.1 * offset.1) as usize;
+        if distance2 > self.sight * self.sight {
+            return false;
         }
+        if distance2 <= 2 || self.view_angle >= 180 {
+            return true;
+        }
+
+        let dot = (offset.0 * self.facing.0 + offset.1 * self.facing.1) as f64;
+        let facing = ((self.facing.0 * self.facing.0 + self.facing.1 * self.facing.1) as f64).sqrt();
+        let cos = dot / ((distance2 as f64).sqrt() * facing);
+        cos >= (self.view_angle as f64).to_radians().cos()
     }
+
+    /// Turn to look at `target`.
+    pub fn face(&mut self, origin: (usize, usize), target: (usize, usize)) {
+        let dx = target.0 as i32 - origin.0 as i32;
+        let dy = target.1 as i32 - origin.1 as i32;
+        if (dx, dy) != (0, 0) {
+            self.facing = (dx.signum(), dy.signum());
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self.state {
+            Awareness::Unaware => "It hasn't noticed you.",
+            Awareness::Suspicious => "It's suspicious.",
+            Awareness::Alert => "It's alert.",
+            Awareness::Searching => "It's searching for you.",
+        }
+    }
+}
+
+impl Archetype {
+    pub fn from_name(name: &str) -> Option<Archetype> {
+        match name {
+            "grunt" => Some(Archetype::Grunt),
+            "sniper" => Some(Archetype::Sniper),
+            "brawler" => Some(Archetype::Brawler),
+            _ => None,
+        }
+    }
+
+    /// How it sees, to begin with.
+    pub fn perception(&self) -> Perception {
+        match *self {
+            Archetype::Grunt => Perception::new(6, 60),
+            Archetype::Sniper => Perception::new(9, 30),
+            Archetype::Brawler => Perception::new(5, 90),
+        }
+    }
+
+    pub fn tree(&self) -> Node {
+        use self::Condition::*;
+        use self::Node::*;
+        use self::Task::*;
+
+        match *self {
+            Archetype::Grunt => Selector(vec![
+                Sequence(vec![Condition(HealthBelow(35)), Condition(Alerted), Task(Flee)]),
+                Sequence(vec![Condition(SeesTarget), Task(CallForHelp)]),
+                Task(Reload),
+                Task(Attack),
+                Task(Chase),
+                Task(Search),
+                Task(Patrol),
+            ]),
+            Archetype::Sniper => Selector(vec![
+                Sequence(vec![Condition(SeesTarget), Task(KeepRange(4))]),
+                Sequence(vec![Condition(HealthBelow(50)), Condition(Alerted), Task(SeekCover)]),
+                Task(Reload),
+                Task(Attack),
+                Sequence(vec![Condition(Alerted), Task(SeekCover)]),
+                Task(Guard),
+            ]),
+            Archetype::Brawler => Selector(vec![
+                Task(Attack),
+                Task(Chase),
+                Task(Search),
+                Task(Patrol),
+            ]),
+        }
+    }
+}
+
+impl specs::Component for Perception {
+    type Storage = specs::VecStorage<Perception>;
+}
+
+impl specs::Component for Behavior {
+    type Storage = specs::VecStorage<Behavior>;
 }
 
 impl specs::Component for ChaseBehavior {
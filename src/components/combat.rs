@@ -15,22 +15,195 @@
 
 use specs;
 
+use super::position::Position;
+use super::status::StatusEffect;
+
+/// How many ticks an explosion stays on screen.
+pub const EXPLOSION_TICKS: usize = 12;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum DamageType {
+    Ballistic,
+    Energy,
+    Blunt,
+}
+
+pub const DAMAGE_TYPES: [DamageType; 3] = [
+    DamageType::Ballistic,
+    DamageType::Energy,
+    DamageType::Blunt,
+];
+
+/// Damage reduction against particular types of damage, on top of
+/// the reduction against everything.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq,Serialize,Deserialize)]
+pub struct Resistances {
+    pub ballistic: usize,
+    pub energy: usize,
+    pub blunt: usize,
+}
+
 #[derive(Clone,Copy,Debug,Eq,PartialEq)]
 pub struct Attack {
     pub damage: (usize, usize),
+    pub damage_type: DamageType,
     pub accuracy: usize,
+    /// Ignores this much of the target's damage reduction.
+    pub penetration: usize,
+    /// Out of 1000.
+    pub crit_chance: usize,
+    /// Damage on a critical hit, as a percentage of the roll.
+    pub crit_multiplier: usize,
     pub source: specs::Entity,
+    /// Applied to the target on a hit.
+    pub status: Option<StatusEffect>,
+    /// Damages walls and doors it hits.
+    pub breach: bool,
 }
 
-#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+/// The attacks that have landed on an entity since combat was last
+/// resolved, in the order they landed.
+#[derive(Clone,Debug)]
+pub struct Attacked {
+    pub attacks: Vec<Attack>,
+}
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub struct DamageReduction {
     pub value: usize,
+    pub resistances: Resistances,
 }
 
-impl specs::Component for Attack {
-    type Storage = specs::VecStorage<Attack>;
+/// Extra damage reduction that wears off.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct TemporaryDamageReduction {
+    pub value: usize,
+    /// The player turn it wears off on.
+    pub expires: u64,
+}
+
+/// Crouching behind half cover turns it into full cover, until the
+/// entity moves.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+pub struct Crouching;
+
+/// A blast that goes off the next time explosions are processed,
+/// then stays on screen for a moment.
+#[derive(Clone,Debug)]
+pub struct Explosion {
+    pub center: Position,
+    pub radius: usize,
+    /// Applied to everything caught in the blast.
+    pub attack: Attack,
+    pub detonated: bool,
+    /// The cells the blast reached.
+    pub cells: Vec<Position>,
+    pub ticks_left: usize,
+}
+
+impl DamageType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DamageType::Ballistic => "ballistic",
+            DamageType::Energy => "energy",
+            DamageType::Blunt => "blunt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DamageType> {
+        match name {
+            "ballistic" => Some(DamageType::Ballistic),
+            "energy" => Some(DamageType::Energy),
+            "blunt" => Some(DamageType::Blunt),
+            _ => None,
+        }
+    }
+}
+
+impl Resistances {
+    pub fn get(&self, damage_type: DamageType) -> usize {
+        match damage_type {
+            DamageType::Ballistic => self.ballistic,
+            DamageType::Energy => self.energy,
+            DamageType::Blunt => self.blunt,
+        }
+    }
+
+    pub fn add(&mut self, other: &Resistances) {
+        self.ballistic += other.ballistic;
+        self.energy += other.energy;
+        self.blunt += other.blunt;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Default::default()
+    }
+}
+
+impl DamageReduction {
+    pub fn new() -> DamageReduction {
+        DamageReduction {
+            value: 0,
+            resistances: Default::default(),
+        }
+    }
+
+    /// How much of a hit of this type the reduction takes off.
+    pub fn against(&self, damage_type: DamageType) -> usize {
+        self.value + self.resistances.get(damage_type)
+    }
+}
+
+impl Attacked {
+    pub fn new() -> Attacked {
+        Attacked {
+            attacks: Vec::new(),
+        }
+    }
+
+    /// Queue up an attack on `target`, after any already landed.
+    pub fn push<A, D>(storage: &mut specs::Storage<Attacked, A, D>, target: specs::Entity, attack: Attack)
+        where A: ::std::ops::Deref<Target=specs::Allocator>,
+              D: ::std::ops::DerefMut<Target=specs::MaskedStorage<Attacked>> {
+        if let Some(pending) = storage.get_mut(target) {
+            pending.attacks.push(attack);
+            return;
+        }
+        let mut pending = Attacked::new();
+        pending.attacks.push(attack);
+        storage.insert(target, pending);
+    }
+}
+
+impl Explosion {
+    pub fn new(center: Position, radius: usize, attack: Attack) -> Explosion {
+        Explosion {
+            center: center,
+            radius: radius,
+            attack: attack,
+            detonated: false,
+            cells: Vec::new(),
+            ticks_left: EXPLOSION_TICKS,
+        }
+    }
+}
+
+impl specs::Component for Attacked {
+    type Storage = specs::VecStorage<Attacked>;
 }
 
 impl specs::Component for DamageReduction {
     type Storage = specs::VecStorage<DamageReduction>;
 }
+
+impl specs::Component for TemporaryDamageReduction {
+    type Storage = specs::HashMapStorage<TemporaryDamageReduction>;
+}
+
+impl specs::Component for Crouching {
+    type Storage = specs::NullStorage<Crouching>;
+}
+
+impl specs::Component for Explosion {
+    type Storage = specs::HashMapStorage<Explosion>;
+}
@@ -20,10 +20,20 @@ use voodoo::overlay::Overlay;
 use voodoo::window::TermCell;
 
 use ::util::bresenham;
-use super::map::Map;
+use super::map::{Map, MapBuilder, Visibility};
 use super::position::Position;
 
+/// A description of how to draw a single cell, which (unlike
+/// `TermCell`) can be saved.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct Glyph {
+    pub ch: char,
+    pub fg: Option<::ui::Color>,
+    pub faint: bool,
+}
+
 pub struct StaticDrawable {
+    pub glyph: Glyph,
     pub tc: TermCell,
 }
 
@@ -42,8 +52,44 @@ pub struct RenderSystem {
 
 }
 
-impl StaticDrawable {
+impl Glyph {
+    pub fn new(ch: char) -> Glyph {
+        Glyph {
+            ch: ch,
+            fg: None,
+            faint: false,
+        }
+    }
+
+    pub fn with_fg(mut self, fg: ::ui::Color) -> Glyph {
+        self.fg = Some(fg);
+        self
+    }
 
+    pub fn faint(mut self) -> Glyph {
+        self.faint = true;
+        self
+    }
+
+    pub fn cell(&self) -> TermCell {
+        let mut tc: TermCell = self.ch.into();
+        if let Some(fg) = self.fg {
+            tc = tc.with_fg(fg.into());
+        }
+        if self.faint {
+            tc = tc.faint();
+        }
+        tc
+    }
+}
+
+impl StaticDrawable {
+    pub fn new(glyph: Glyph) -> StaticDrawable {
+        StaticDrawable {
+            glyph: glyph,
+            tc: glyph.cell(),
+        }
+    }
 }
 
 impl specs::Component for StaticDrawable {
@@ -98,14 +144,18 @@ impl specs::System<()> for RenderSystem {
     fn run(&mut self, arg: specs::RunArg, _: ()) {
         use specs::Join;
 
-        let (map, drawables, lines, positions, cameras, mut targets) = arg.fetch(|world| {
+        let (map, entities, builders, chasers, drawables, lines, explosions, positions, cameras, mut targets) = arg.fetch(|world| {
             let map = world.read_resource::<Map>();
+            let entities = world.entities();
+            let builders = world.read::<MapBuilder>();
+            let chasers = world.read::<super::ai::ChaseBehavior>();
             let drawables = world.read::<StaticDrawable>();
             let lines = world.read::<LineDrawable>();
+            let explosions = world.read::<super::combat::Explosion>();
             let positions = world.read::<Position>();
             let cameras = world.write::<super::camera::Camera>();
             let targets = world.write::<DrawableRender>();
-            (map, drawables, lines, positions, cameras, targets)
+            (map, entities, builders, chasers, drawables, lines, explosions, positions, cameras, targets)
         });
 
         for target in (&mut targets).iter() {
@@ -131,10 +181,35 @@ impl specs::System<()> for RenderSystem {
             }
         }
 
-        for (drawable, position) in (&drawables, &positions).iter() {
+        let reveal_all = (&builders).iter().next().is_some();
+
+        for (entity, drawable, position) in (&entities, &drawables, &positions).iter() {
+            // Enemies are only shown while in view; everything else
+            // is remembered where it was last seen
+            let tc = match map.visibility(position.x, position.y) {
+                _ if reveal_all => drawable.tc,
+                Visibility::Visible => drawable.tc,
+                Visibility::Remembered if chasers.get(entity).is_none() => drawable.tc.faint(),
+                _ => continue,
+            };
+
             for (camera, target) in (&cameras, &mut targets).iter() {
                 if let Some(point) = position.relative_to(&camera) {
-                    target.overlay.blend_at(point, drawable.tc);
+                    target.overlay.blend_at(point, tc);
+                }
+            }
+        }
+
+        // Explosions go over everything, but only where they can be seen
+        for explosion in (&explosions).iter() {
+            for cell in explosion.cells.iter().filter(|c| map.is_visible(c.x, c.y)) {
+                for (camera, target) in (&cameras, &mut targets).iter() {
+                    if let Some(point) = cell.relative_to(&camera) {
+                        let mut tc: TermCell = '*'.into();
+                        tc.fg = Some(ColorValue::Yellow);
+                        tc.bg = Some(ColorValue::Red);
+                        target.overlay.put_at(point, tc);
+                    }
                 }
             }
         }
@@ -15,12 +15,14 @@
 
 use specs;
 
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub struct Health {
     pub health: usize,
     pub base_health: usize,
     pub max_health: usize,
 }
 
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub struct Cover {
     pub penalty: i32,
 }
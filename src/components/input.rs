@@ -20,8 +20,11 @@ use specs;
 use termion::event::Key;
 use voodoo::window::{Point, Window};
 
+use ::events::EventBus;
+use ::keymap::{Action, Keymap};
 use ::systems::ui;
 use ::util::Direction;
+use super::action;
 use super::map::Map;
 use super::position::Position;
 
@@ -37,15 +40,22 @@ pub trait OffsetMovable {
 
 pub struct InputSystem {
     pub inputs: mpsc::Receiver<Event>,
-    message_queue: mpsc::Sender<String>,
-    ai_begin: mpsc::Sender<()>,
-    ai_end: mpsc::Receiver<()>,
-    ai_turn: Cell<bool>,
+    events: EventBus,
+    /// What the player does with their turn.
+    actions: mpsc::Sender<action::Action>,
+    turn_end: mpsc::Receiver<()>,
+    /// Whether the rest of the world is still acting after our turn.
+    waiting: Cell<bool>,
+    descend: mpsc::Sender<()>,
     state: State,
     transitions: ::screen::TransitionChannel,
     sub_screen: mpsc::Sender<::screen::SubScreenEvent<::screen::game::SubGameScreen>>,
     inv_valid: bool,
     inv_list: ::ui::List<::components::player::Item>,
+    keymap: Keymap,
+    /// What the shot being aimed is expected to do, shown beside the
+    /// help while targeting.
+    preview: Vec<(String, String)>,
 }
 
 #[derive(Clone,Copy,Debug,Eq,PartialEq)]
@@ -60,29 +70,86 @@ pub enum Event {
 enum State {
     Toplevel,
     Examining,
-    Targeting(bool),
+    Targeting(Aim),
     Inventory,
     Equip,
+    /// Reading back through the messages.
+    History,
+}
+
+/// What the targeting line is for.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+enum Aim {
+    Primary,
+    Secondary,
+    /// Throwing the explosive at this index of the inventory.
+    Throw(usize),
+}
+
+/// The lines of the targeting preview, as labels and values. The
+/// outcome comes first, so that it fills the first column, and the
+/// modifiers that went into the hit chance follow.
+fn describe_prediction(prediction: &::util::combat::Prediction) -> Vec<(String, String)> {
+    if let Some(reason) = prediction.blocked {
+        return vec![("Hit".into(), reason.into())];
+    }
+
+    let mut lines = vec![("Hit".into(), format!("{}%", prediction.chance / 10))];
+    lines.push(("Damage".into(), format!("{}-{} {}", prediction.damage.0, prediction.damage.1,
+                                         prediction.attack.damage_type.name())));
+    if prediction.attack.crit_chance > 0 {
+        lines.push(("Crit".into(), format!("{}% {}-{}", prediction.attack.crit_chance / 10,
+                                           prediction.crit_damage.0, prediction.crit_damage.1)));
+    }
+    if prediction.radius > 0 {
+        lines.push(("Blast".into(), format!("{}", prediction.radius)));
+    }
+    // Start the modifiers on a column of their own
+    while lines.len() % 4 != 0 {
+        lines.push((String::new(), String::new()));
+    }
+
+    for &(name, value) in prediction.hit.modifiers.iter() {
+        lines.push((name.into(), format!("{:+}%", value / 10)));
+    }
+    if prediction.intercepted > 0 {
+        lines.push(("In the way".into(), format!("{:+}%", -prediction.intercepted / 10)));
+    }
+    lines
+}
+
+/// Print as much of `text` as fits in the window at `x`.
+fn print_clipped(window: &mut Window, x: u16, y: u16, text: &str) {
+    if x >= window.width {
+        return;
+    }
+    let clipped: String = text.chars().take((window.width - x) as usize).collect();
+    window.print_at(Point::new(x, y), &clipped);
 }
 
 impl InputSystem {
     pub fn new(inv_list: ::ui::List<::components::player::Item>,
+               keymap: Keymap,
                transitions: ::screen::TransitionChannel,
                sub_screen: mpsc::Sender<::screen::SubScreenEvent<::screen::game::SubGameScreen>>,
-               message_queue: mpsc::Sender<String>,
-               ai_begin: mpsc::Sender<()>, ai_end: mpsc::Receiver<()>) -> (InputSystem, mpsc::Sender<Event>) {
+               events: EventBus,
+               actions: mpsc::Sender<action::Action>, turn_end: mpsc::Receiver<()>,
+               descend: mpsc::Sender<()>) -> (InputSystem, mpsc::Sender<Event>) {
         let (tx, rx) = mpsc::channel();
         (InputSystem {
             inputs: rx,
-            message_queue: message_queue,
-            ai_begin: ai_begin,
-            ai_end: ai_end,
-            ai_turn: Cell::new(false),
+            events: events,
+            actions: actions,
+            turn_end: turn_end,
+            waiting: Cell::new(false),
+            descend: descend,
             state: State::Toplevel,
             transitions: transitions,
             sub_screen: sub_screen,
             inv_valid: false,
             inv_list: inv_list,
+            keymap: keymap,
+            preview: Vec::new(),
         }, tx)
     }
 
@@ -96,76 +163,98 @@ impl InputSystem {
         }
     }
 
-    fn take_items<'a, I, G>(&self, direction: Direction, map: &mut Map, movables: I, grabbables: G)
-        -> Vec<specs::Entity>
-        where I: Iterator<Item=(&'a Movable, &'a Position, &'a mut super::player::Inventory)>,
-              G: ::util::HasStorage<super::player::Grabbable>, {
-        let mut to_delete = Vec::new();
-        let offset = direction.offset();
-
-        for (_, position, inventory) in movables {
-            let new_x = position.x as i32 + offset.0;
-            let new_y = position.y as i32 + offset.1;
+    /// Take an action as the player's turn, and wait for it and
+    /// everyone else's to play out.
+    fn act(&self, action: action::Action) {
+        self.actions.send(action).unwrap();
+        self.waiting.set(true);
+    }
 
-            let new_x = if new_x < 0 { 0 } else { new_x as usize };
-            let new_y = if new_y < 0 { 0 } else { new_y as usize };
+    /// The key bound to an action and what it does, for each action
+    /// available in the current state.
+    fn help(&self) -> Vec<(String, &'static str)> {
+        use self::State::*;
+        let keymap = &self.keymap;
 
-            if let Some(entity) = map.contents(new_x, new_y) {
-                if let Some(&super::player::Grabbable(ref item)) = grabbables.get(entity) {
-                    inventory.contents.push(item.clone());
-                    self.message_queue.send(format!("Picked up {}", item.name)).unwrap();
-                    to_delete.push(entity);
-                }
-            }
+        match self.state {
+            Toplevel => vec![
+                (keymap.movement_label(), "Move"),
+                (keymap.diagonal_label(), "Diag"),
+                (keymap.label(Action::Wait), "Wait"),
+                (keymap.label(Action::Cancel), "Quit"),
+                (keymap.label(Action::Examine), "Examine"),
+                (keymap.label(Action::FirePrimary), "Fire"),
+                (keymap.label(Action::FireSecondary), "Melee"),
+                // Kept short so that four columns fit in the panel
+                (keymap.label(Action::OpenInventory), "Items"),
+                (keymap.label(Action::Descend), "Descend"),
+                (keymap.label(Action::Reload), "Reload"),
+                (keymap.label(Action::Crouch), "Crouch"),
+                (keymap.label(Action::Sneak), "Sneak"),
+                (keymap.label(Action::MessageHistory), "History"),
+                (keymap.label(Action::SaveAndQuit), "Save"),
+            ],
+
+            Examining => vec![
+                (keymap.label(Action::Cancel), "Cancel"),
+                (keymap.movement_label(), "Move"),
+                ("Mouse".into(), "Describe"),
+            ],
+
+            Targeting(aim) => vec![
+                (keymap.label(Action::Cancel), "Cancel"),
+                (keymap.movement_label(), "Manual Aim"),
+                ("Mouse".into(), "Choose Target"),
+                (keymap.label(Action::Confirm), if let Aim::Throw(_) = aim { "Throw" } else { "Confirm Fire" }),
+            ],
+
+            Inventory | Equip => vec![
+                (keymap.label(Action::Cancel), "Cancel"),
+                (keymap.label(Action::MoveUp) + &keymap.label(Action::MoveDown), "Scroll"),
+                (keymap.label(Action::Confirm), if self.state == Inventory { "Use/Equip" } else { "Unequip" }),
+                (keymap.label(Action::SwitchList), if self.state == Inventory { "Equip" } else { "Inventory" }),
+            ],
+
+            History => vec![
+                (keymap.label(Action::Cancel), "Close"),
+                (keymap.label(Action::MoveUp) + &keymap.label(Action::MoveDown), "Scroll"),
+                (keymap.label(Action::ScrollUp) + &keymap.label(Action::ScrollDown), "Page"),
+                (keymap.label(Action::SwitchList), "Filter"),
+            ],
         }
-
-        to_delete
-    }
-
-    fn end_turn(&self) {
-        self.ai_begin.send(()).unwrap();
-        self.ai_turn.set(false);
     }
 
     fn render(&self, window: &mut Window) {
-        use self::State::*;
         window.clear();
 
-        match self.state {
-            Toplevel => {
-                window.print_at(Point::new(0, 0), "WASD—Move");
-                window.print_at(Point::new(0, 1), "   1—Examine");
-                window.print_at(Point::new(0, 2), "   2—Fire Primary");
-                window.print_at(Point::new(0, 3), "   3—Fire Secondary");
-
-                window.print_at(Point::new(20, 0), "I—Inventory");
-            }
-
-            Examining => {
-                window.print_at(Point::new(0, 0), "  Esc—Cancel");
-                window.print_at(Point::new(0, 1), " WASD—Move");
-                window.print_at(Point::new(0, 2), "Mouse—Describe");
-            }
-
-            Targeting(_) => {
-                window.print_at(Point::new(0, 0), "  Esc—Cancel");
-                window.print_at(Point::new(0, 1), " WASD—Manual Aim");
-                window.print_at(Point::new(0, 2), "Mouse—Choose Target");
-                window.print_at(Point::new(0, 3), "Space—Confirm Fire");
+        // Four lines to a column, with the keys right-aligned, and
+        // each column as narrow as it can be. The panel is too narrow
+        // for both the help and a preview, so the preview replaces the
+        // help while there is one.
+        let mut x = 0;
+        if self.preview.is_empty() {
+            for entries in self.help().chunks(4) {
+                let width = entries.iter().map(|&(ref key, _)| key.chars().count()).max().unwrap_or(0);
+                let mut column_width = 0;
+                for (row, &(ref key, description)) in entries.iter().enumerate() {
+                    let line = format!("{:>width$}—{}", key, description, width = width);
+                    column_width = ::std::cmp::max(column_width, line.chars().count());
+                    print_clipped(window, x, row as u16, &line);
+                }
+                x += column_width as u16 + 1;
             }
+        }
 
-            Inventory | Equip => {
-                window.print_at(Point::new(0, 0), "  Esc—Cancel");
-                window.print_at(Point::new(0, 1), "   WS—Scroll");
-                if self.state == Inventory {
-                    window.print_at(Point::new(0, 2), "Space—Equip");
-                    window.print_at(Point::new(0, 3), "  Tab—Equip");
-                }
-                else {
-                    window.print_at(Point::new(0, 2), "Space—Unequip");
-                    window.print_at(Point::new(0, 3), "  Tab—Inventory");
-                }
+        // The values in the preview are aligned
+        for entries in self.preview.chunks(4) {
+            let width = entries.iter().map(|&(ref label, _)| label.chars().count()).max().unwrap_or(0);
+            let mut column_width = 0;
+            for (row, &(ref label, ref value)) in entries.iter().enumerate() {
+                let line = format!("{:width$} {}", label, value, width = width);
+                column_width = ::std::cmp::max(column_width, line.chars().count());
+                print_clipped(window, x, row as u16, &line);
             }
+            x += column_width as u16 + 2;
         }
     }
 }
@@ -175,9 +264,9 @@ impl specs::System<()> for InputSystem {
         use specs::Join;
         use self::State::*;
 
-        if self.ai_turn.get() {
-            if let Ok(()) = self.ai_end.try_recv() {
-                self.ai_turn.set(false);
+        if self.waiting.get() {
+            if let Ok(()) = self.turn_end.try_recv() {
+                self.waiting.set(false);
             }
             else {
                 // Required to make specs not panic
@@ -188,94 +277,89 @@ impl specs::System<()> for InputSystem {
 
         match self.state {
             Toplevel => {
-                let (mut res, mut map, focused, mut movables, mut positions, mut lines, mut inventories, grabbables) = arg.fetch(|world| {
+                let (mut res, mut messages, map, focused, mut movables, positions, mut lines) = arg.fetch(|world| {
                     (
                         world.write_resource::<ui::CommandPanelResource>(),
-                        world.write_resource::<super::map::Map>(),
+                        world.write_resource::<ui::MessagesPanelResource>(),
+                        world.read_resource::<super::map::Map>(),
                         world.read::<super::ui::Focus>(),
                         world.write::<Movable>(),
-                        world.write::<Position>(),
+                        world.read::<Position>(),
                         world.write::<super::drawable::LineDrawable>(),
-                        world.write::<super::player::Inventory>(),
-                        world.read::<super::player::Grabbable>(),
                     )
                 });
                 for event in self.inputs.try_iter() {
-                    match event {
-                        Event::Key(Key::Esc) => self.transitions.send(::screen::StateTransition::Quit).unwrap(),
-
-                        Event::Key(Key::Char('w')) => {
-                            for entity in self.take_items(Direction::Up, &mut map,
-                                                          (&movables, &positions, &mut inventories).iter(),
-                                                          &grabbables) {
-                                if let Some(pos) = positions.get(entity) {
-                                    map.vacate(pos.x, pos.y);
-                                }
-                                positions.remove(entity);
-                                arg.delete(entity);
-                            }
-                            self.process_movement(Direction::Up, &mut map,
-                                                  (&movables, &mut positions).iter());
-                            self.end_turn();
-                        }
-                        Event::Key(Key::Char('s')) => {
-                            for entity in self.take_items(Direction::Down, &mut map,
-                                                          (&movables, &positions, &mut inventories).iter(),
-                                                          &grabbables) {
-                                if let Some(pos) = positions.get(entity) {
-                                    map.vacate(pos.x, pos.y);
-                                }
-                                positions.remove(entity);
-                                arg.delete(entity);
-                            }
+                    let action = match event {
+                        Event::Key(key) => self.keymap.action(key),
+                        _ => None,
+                    };
+
+                    if let Some(direction) = action.and_then(|a| a.direction()) {
+                        self.act(action::Action::Move(direction));
+                        // Let everyone else act before taking more input
+                        break;
+                    }
 
-                            self.process_movement(Direction::Down, &mut map,
-                                                  (&movables, &mut positions).iter());
-                            self.end_turn();
+                    match action {
+                        Some(Action::Cancel) => self.transitions.send(::screen::StateTransition::Quit).unwrap(),
+                        Some(Action::SaveAndQuit) => self.transitions.send(::screen::StateTransition::SaveAndQuit).unwrap(),
+                        Some(Action::Wait) => {
+                            self.act(action::Action::Wait);
+                            break;
                         }
-                        Event::Key(Key::Char('a')) => {
-                            for entity in self.take_items(Direction::Left, &mut map,
-                                                          (&movables, &positions, &mut inventories).iter(),
-                                                          &grabbables) {
-                                if let Some(pos) = positions.get(entity) {
-                                    map.vacate(pos.x, pos.y);
-                                }
-                                positions.remove(entity);
-                                arg.delete(entity);
+                        Some(Action::Reload) => {
+                            self.act(action::Action::Reload);
+                            break;
+                        }
+                        Some(Action::Crouch) => {
+                            self.act(action::Action::Crouch);
+                            break;
+                        }
+                        Some(Action::Sneak) => {
+                            self.act(action::Action::Sneak);
+                            break;
+                        }
+
+                        Some(Action::Descend) => {
+                            let on_stairs = (&focused, &positions).iter().next().map_or(false, |(_, pos)| {
+                                map.cell(pos.x, pos.y) == super::map::MapCell::Stairs
+                            });
+                            if on_stairs {
+                                self.descend.send(()).unwrap();
+                            }
+                            else {
+                                self.events.notice("There are no stairs here.");
                             }
+                        }
 
-                            self.process_movement(Direction::Left, &mut map,
-                                                  (&movables, &mut positions).iter());
-                            self.end_turn();
+                        // A page at a time, keeping a line from the last
+                        Some(Action::ScrollUp) => {
+                            let page = messages.window.height as usize - 1;
+                            messages.scroll += page;
+                        }
+                        Some(Action::ScrollDown) => {
+                            let page = messages.window.height as usize - 1;
+                            messages.scroll = messages.scroll.saturating_sub(page);
                         }
-                        Event::Key(Key::Char('d')) => {
-                            for entity in self.take_items(Direction::Right, &mut map,
-                                                          (&movables, &positions, &mut inventories).iter(),
-                                                          &grabbables) {
-                                if let Some(pos) = positions.get(entity) {
-                                    map.vacate(pos.x, pos.y);
-                                }
-                                positions.remove(entity);
-                                arg.delete(entity);
-                            }
 
-                            self.process_movement(Direction::Right, &mut map,
-                                                  (&movables, &mut positions).iter());
-                            self.end_turn();
+                        Some(Action::MessageHistory) => {
+                            self.state = History;
+                            self.sub_screen.send(::screen::SubScreenEvent::Push(::screen::game::SubGameScreen::History)).unwrap();
+                            break;
                         }
 
-                        Event::Key(Key::Char('i')) => {
+                        Some(Action::OpenInventory) => {
                             self.state = Inventory;
                             self.sub_screen.send(::screen::SubScreenEvent::Push(::screen::game::SubGameScreen::Inventory)).unwrap();
                             break;
                         }
 
-                        Event::Key(Key::Char('1')) => {
+                        Some(Action::Examine) => {
                             self.state = Examining;
                             break;
                         }
 
-                        Event::Key(Key::Char(c @ '2')) | Event::Key(Key::Char(c @ '3')) => {
+                        Some(action @ Action::FirePrimary) | Some(action @ Action::FireSecondary) => {
                             movables.clear();
 
                             let mut start_pos = Position { x: 0, y: 0 };
@@ -290,7 +374,7 @@ impl specs::System<()> for InputSystem {
                                 end: start_pos,
                             });
                             movables.insert(e, Movable);
-                            self.state = Targeting(c == '3');
+                            self.state = Targeting(if action == Action::FireSecondary { Aim::Secondary } else { Aim::Primary });
                             break;
                         }
 
@@ -302,67 +386,64 @@ impl specs::System<()> for InputSystem {
             }
 
             Examining => {
-                let (mut res, mut map, cameras, movables, mut positions, cover, health, grabbable) = arg.fetch(|world| {
+                let (mut res, map, cameras, cover, health, grabbable, perceptions, statuses) = arg.fetch(|world| {
                     (
                         world.write_resource::<ui::CommandPanelResource>(),
-                        world.write_resource::<super::map::Map>(),
+                        world.read_resource::<super::map::Map>(),
                         world.read::<super::camera::Camera>(),
-                        world.read::<Movable>(),
-                        world.write::<Position>(),
                         world.read::<super::health::Cover>(),
                         world.read::<super::health::Health>(),
                         world.read::<super::player::Grabbable>(),
+                        world.read::<super::ai::Perception>(),
+                        world.read::<super::status::StatusEffects>(),
                     )
                 });
                 for event in self.inputs.try_iter() {
-                    match event {
-                        Event::Key(Key::Esc) => self.state = Toplevel,
+                    let action = match event {
+                        Event::Key(key) => self.keymap.action(key),
+                        _ => None,
+                    };
+
+                    if let Some(direction) = action.and_then(|a| a.direction()) {
+                        self.act(action::Action::Move(direction));
+                        break;
+                    }
 
-                        Event::Key(Key::Char('w')) => {
-                            self.process_movement(Direction::Up, &mut map,
-                                                  (&movables, &mut positions).iter());
-                            self.end_turn();
-                        }
-                        Event::Key(Key::Char('s')) => {
-                            self.process_movement(Direction::Down, &mut map,
-                                                  (&movables, &mut positions).iter());
-                            self.end_turn();
-                        }
-                        Event::Key(Key::Char('a')) => {
-                            self.process_movement(Direction::Left, &mut map,
-                                                  (&movables, &mut positions).iter());
-                            self.end_turn();
-                        }
-                        Event::Key(Key::Char('d')) => {
-                            self.process_movement(Direction::Right, &mut map,
-                                                  (&movables, &mut positions).iter());
-                            self.end_turn();
-                        }
+                    match (action, event) {
+                        (Some(Action::Cancel), _) => self.state = Toplevel,
 
-                        Event::MouseRelease(point) => {
+                        (_, Event::MouseRelease(point)) => {
                             let camera = cameras.iter().next().unwrap();
                             let x = (camera.position.x + point.x) as usize;
                             let y = (camera.position.y + point.y) as usize;
 
                             if let Some(entity) = map.contents(x, y) {
-                                self.message_queue.send(format!("Position: {}, {}", x, y)).unwrap();
+                                self.events.notice(format!("Position: {}, {}", x, y));
                                 if let Some(h) = health.get(entity) {
-                                    self.message_queue.send(format!("Health: {}/{}", h.health, h.max_health)).unwrap();
+                                    self.events.notice(format!("Health: {}/{}", h.health, h.max_health));
                                 }
                                 if let Some(_) = cover.get(entity) {
-                                    self.message_queue.send("Provides cover".into()).unwrap();
+                                    self.events.notice("Provides cover");
+                                }
+                                if let Some(p) = perceptions.get(entity) {
+                                    self.events.notice(p.describe());
+                                }
+                                if let Some(effects) = statuses.get(entity) {
+                                    for effect in effects.effects.iter() {
+                                        self.events.notice(format!("Status: {}", effect.describe()));
+                                    }
                                 }
                                 if let Some(g) = grabbable.get(entity) {
                                     use ui::list::ListRenderable;
 
-                                    self.message_queue.send("Dropped item".into()).unwrap();
+                                    self.events.notice("Dropped item");
                                     for line in g.0.render() {
-                                        self.message_queue.send(line.into()).unwrap();
+                                        self.events.notice(line);
                                     }
                                 }
                             }
                             else {
-                                self.message_queue.send("Nothing here.".into()).unwrap();
+                                self.events.notice("Nothing here.");
                             }
                         }
 
@@ -373,12 +454,12 @@ impl specs::System<()> for InputSystem {
                 self.render(&mut res.window);
             }
 
-            Targeting(is_melee) => {
+            Targeting(aim) => {
                 let (
                     mut res, mut map, entities,
                     cameras, focused, mut movables,
-                    mut lines, covers, healths,
-                    mut attacked, equipped,
+                    mut lines, covers, crouching, dr, temp_dr, healths,
+                    equipped, statuses,
                 ) = arg.fetch(|world| {
                     (
                         world.write_resource::<ui::CommandPanelResource>(),
@@ -389,28 +470,29 @@ impl specs::System<()> for InputSystem {
                         world.write::<Movable>(),
                         world.write::<super::drawable::LineDrawable>(),
                         world.read::<super::health::Cover>(),
+                        world.read::<super::combat::Crouching>(),
+                        world.read::<super::combat::DamageReduction>(),
+                        world.read::<super::combat::TemporaryDamageReduction>(),
                         world.read::<super::health::Health>(),
-                        world.write::<super::combat::Attack>(),
                         world.read::<super::player::Equip>(),
+                        world.read::<super::status::StatusEffects>(),
                     )
                 });
 
                 for event in self.inputs.try_iter() {
-                    match event {
-                        Event::Key(Key::Char('w')) =>
-                            self.process_movement(Direction::Up, &mut map,
-                                                  (&movables, &mut lines).iter()),
-                        Event::Key(Key::Char('s')) =>
-                            self.process_movement(Direction::Down, &mut map,
-                                                  (&movables, &mut lines).iter()),
-                        Event::Key(Key::Char('a')) =>
-                            self.process_movement(Direction::Left, &mut map,
-                                                  (&movables, &mut lines).iter()),
-                        Event::Key(Key::Char('d')) =>
-                            self.process_movement(Direction::Right, &mut map,
-                                                  (&movables, &mut lines).iter()),
-
-                        Event::MouseHover(point) | Event::MouseRelease(point) => {
+                    let action = match event {
+                        Event::Key(key) => self.keymap.action(key),
+                        _ => None,
+                    };
+
+                    if let Some(direction) = action.and_then(|a| a.direction()) {
+                        self.process_movement(direction, &mut map,
+                                              (&movables, &mut lines).iter());
+                        continue;
+                    }
+
+                    match (action, event) {
+                        (_, Event::MouseHover(point)) | (_, Event::MouseRelease(point)) => {
                             let camera = cameras.iter().next().unwrap();
                             for (_, movable) in (&movables, &mut lines).iter() {
                                 movable.end.x = (camera.position.x + point.x) as usize;
@@ -418,49 +500,24 @@ impl specs::System<()> for InputSystem {
                             }
                         }
 
-                        Event::Key(Key::Esc) | Event::Key(Key::Char('3')) |
-                        Event::Key(Key::Char('q')) | Event::Key(Key::Char(' ')) => {
+                        // Pressing a fire key again cancels, like Cancel
+                        (Some(Action::Cancel), _) | (Some(Action::Confirm), _) |
+                        (Some(Action::FirePrimary), _) | (Some(Action::FireSecondary), _) => {
                             let mut points = None;
-                            let mut attacker = None;
                             for (entity, line, _) in (&entities, &lines, &movables).iter() {
                                 points = Some((line.start, line.end));
                                 arg.delete(entity);
                             }
                             for (entity, _) in (&entities, &focused).iter() {
                                 movables.insert(entity, Movable);
-                                attacker = Some((entity, equipped.get(entity).unwrap()));
                             }
                             self.state = Toplevel;
 
-                            if event == Event::Key(Key::Char(' ')) {
-                                self.end_turn();
-
-                                if let (Some((start, end)), Some((attacker, equip))) = (points, attacker) {
-                                    match ::util::combat::resolve(&map, attacker, &equip, start, end, is_melee, &healths, &covers) {
-                                        ::util::combat::CombatResult::NothingEquipped => {
-                                            self.message_queue.send("You have nothing equipped!".into()).unwrap();
-                                        }
-                                        ::util::combat::CombatResult::Miss => {
-                                            self.message_queue.send("You missed!".into()).unwrap();
-                                        }
-                                        ::util::combat::CombatResult::HitNothing => {
-                                            self.message_queue.send("You hit nothing.".into()).unwrap();
-                                        }
-                                        ::util::combat::CombatResult::HitEnvironment => {
-                                            self.message_queue.send("You hit a wall.".into()).unwrap();
-                                        }
-                                        ::util::combat::CombatResult::HitEntity(target, pos, attack) => {
-                                            self.message_queue.send(format!("Targeted {}, {}", pos.x, pos.y)).unwrap();
-                                            attacked.insert(target, attack);
-                                        }
-                                        ::util::combat::CombatResult::OutOfRange => {
-                                            self.message_queue.send("You can't hit that with a melee weapon.".into()).unwrap();
-                                        }
-                                    }
-                                }
-                                else {
-                                    panic!("No attacker/no Equip/no target found? {:?} {:?}", points, attacker);
-                                }
+                            if let (Some(Action::Confirm), Some((_, end))) = (action, points) {
+                                self.act(match aim {
+                                    Aim::Throw(index) => action::Action::Use { index: index, target: Some(end) },
+                                    _ => action::Action::Attack { target: end, melee: aim == Aim::Secondary },
+                                });
                             }
 
                             break;
@@ -470,21 +527,42 @@ impl specs::System<()> for InputSystem {
                     }
                 }
 
+                // Show the odds of the shot as it's lined up
+                self.preview.clear();
+                let line = (&lines, &movables).iter().next().map(|(line, _)| (line.start, line.end));
+                let shooter = (&entities, &focused, &equipped).iter().next();
+                if let (Targeting(aim), Some((start, end)), Some((attacker, _, equip))) = (self.state, line, shooter) {
+                    match aim {
+                        // Throws don't roll to hit
+                        Aim::Throw(_) => {}
+                        _ => {
+                            let prediction = ::util::combat::predict(
+                                &map, attacker, equip, start, end, aim == Aim::Secondary,
+                                &healths, &covers, &statuses, &crouching, &dr, &temp_dr);
+                            self.preview = match prediction {
+                                Some(prediction) => describe_prediction(&prediction),
+                                None if map.contents(end.x, end.y).is_none() || start == end =>
+                                    vec![("Hit".into(), "no target".into())],
+                                None => vec![("Hit".into(), "nothing equipped".into())],
+                            };
+                        }
+                    }
+                }
+
                 self.render(&mut res.window);
             }
 
             Inventory | Equip => {
-                let (
-                    mut res, mut inv, focused, mut dr, mut health, mut equipped, mut inventory,
-                ) = arg.fetch(|world| {
+                let (mut res, mut inv, focused, mut movables, positions, mut lines, equipped, inventory) = arg.fetch(|world| {
                     (
                         world.write_resource::<ui::CommandPanelResource>(),
                         world.write_resource::<ui::InventoryPanelResource>(),
                         world.read::<super::ui::Focus>(),
-                        world.write::<super::combat::DamageReduction>(),
-                        world.write::<super::health::Health>(),
-                        world.write::<super::player::Equip>(),
-                        world.write::<super::player::Inventory>(),
+                        world.write::<Movable>(),
+                        world.read::<Position>(),
+                        world.write::<super::drawable::LineDrawable>(),
+                        world.read::<super::player::Equip>(),
+                        world.read::<super::player::Inventory>(),
                     )
                 });
 
@@ -497,36 +575,37 @@ impl specs::System<()> for InputSystem {
                             self.inv_list.contents = equip.list_equipped().iter().map(|x| (*x).clone()).collect();
                         }
                     }
+                    // Stay put in the list, unless what was at the
+                    // end has gone
+                    if self.inv_list.cursor >= self.inv_list.contents.len() {
+                        self.inv_list.cursor = self.inv_list.contents.len().saturating_sub(1);
+                    }
 
                     self.inv_valid = true;
                 }
 
                 for event in self.inputs.try_iter() {
-                    match event {
-                        Event::Key(Key::Esc) => {
+                    let action = match event {
+                        Event::Key(key) => self.keymap.action(key),
+                        _ => None,
+                    };
+
+                    match action {
+                        Some(Action::Cancel) => {
                             self.sub_screen.send(::screen::SubScreenEvent::Pop).unwrap();
                             self.state = Toplevel;
 
-                            if let Some((_, _, inventory)) = (&focused, &equipped, &mut inventory).iter().next() {
-                                if self.state == Inventory {
-                                    inventory.contents.clone_from(&self.inv_list.contents);
-                                }
-                            }
-
                             self.inv_valid = false;
                             self.inv_list.cursor = 0;
                             self.inv_list.contents.clear();
                         }
 
-                        Event::Key(Key::Char('w')) => self.inv_list.move_cursor_up(),
-                        Event::Key(Key::Char('s')) => self.inv_list.move_cursor_down(),
+                        Some(Action::MoveUp) => self.inv_list.move_cursor_up(),
+                        Some(Action::MoveDown) => self.inv_list.move_cursor_down(),
 
-                        Event::Key(Key::Char('\t')) => {
+                        Some(Action::SwitchList) => {
                             self.sub_screen.send(::screen::SubScreenEvent::Pop).unwrap();
                             if self.state == Inventory {
-                                if let Some((_, _, inventory)) = (&focused, &equipped, &mut inventory).iter().next() {
-                                    inventory.contents.clone_from(&self.inv_list.contents);
-                                }
                                 self.state = Equip;
                                 self.sub_screen.send(::screen::SubScreenEvent::Push(::screen::game::SubGameScreen::Equip)).unwrap();
                             }
@@ -540,64 +619,44 @@ impl specs::System<()> for InputSystem {
                             break;
                         }
 
-                        Event::Key(Key::Char(' ')) => {
-                            if self.state == Inventory {
-                                let result = if let Some(item) = self.inv_list.get_selected() {
-                                    if let Some((_, equip, _)) = (&focused, &mut equipped, &inventory).iter().next() {
-                                        let item = equip.equip(item.clone());
-
-                                        // Recompute health boost, damage reduction
-                                        if let Some((_, dr, hp)) = (&focused, &mut dr, &mut health).iter().next() {
-                                            dr.value = 0;
-                                            hp.max_health = hp.base_health;
-                                            for item in equip.list_equipped() {
-                                                if let super::player::ItemKind::Armor { health, damage_reduction } = item.kind {
-                                                    dr.value += damage_reduction;
-                                                    hp.max_health += health;
-                                                }
-                                            }
-
-                                            if dr.value > 0 {
-                                                self.message_queue.send(format!("DR: {}", dr.value)).unwrap();
-                                            }
-                                        }
-
-                                        Some((item, self.inv_list.cursor))
-                                    }
-                                    else {
-                                        None
-                                    }
-                                } else { None };
-
-                                if let Some((old_item, idx)) = result {
-                                    if let Some(item) = old_item {
-                                        self.inv_list.contents[idx] = item;
-                                    }
-                                    else {
-                                        self.inv_list.move_cursor_up();
-                                        self.inv_list.contents.remove(idx);
-                                    }
-                                }
-                            }
-                            else {
-                                let unequipped = if let Some(item) = self.inv_list.get_selected() {
-                                    let slot = item.slot.expect("Can't unequip unequippable item!");
-                                    if let Some((_, equip, _)) = (&focused, &mut equipped, &inventory).iter().next() {
-                                        equip.unequip(slot).expect("Can't unequip slot that was equipped!");
-                                        Some((item.clone(), self.inv_list.cursor))
-                                    }
-                                    else {
-                                        None
-                                    }
-                                } else { None };
-
-                                if let Some((old_item, idx)) = unequipped {
-                                    if let Some((_, _, inventory)) = (&focused, &mut equipped, &mut inventory).iter().next() {
-                                        inventory.contents.push(old_item.clone());
-                                        self.inv_list.move_cursor_up();
-                                        self.inv_list.contents.remove(idx);
+                        Some(Action::Confirm) => {
+                            use super::player::{Effect, ItemKind};
+
+                            let idx = self.inv_list.cursor;
+                            let selected = self.inv_list.get_selected().map(|item| (item.kind, item.slot));
+                            let chosen = match (self.state, selected) {
+                                (Inventory, Some((ItemKind::Consumable(Effect::Explode { .. }), _))) => {
+                                    // Close the inventory and aim the throw
+                                    self.sub_screen.send(::screen::SubScreenEvent::Pop).unwrap();
+                                    self.inv_valid = false;
+                                    self.inv_list.cursor = 0;
+                                    self.inv_list.contents.clear();
+
+                                    movables.clear();
+                                    let mut start_pos = Position { x: 0, y: 0 };
+                                    for (_, pos) in (&focused, &positions).iter() {
+                                        start_pos = *pos;
                                     }
+                                    let e = arg.create();
+                                    lines.insert(e, super::drawable::LineDrawable {
+                                        start: start_pos,
+                                        end: start_pos,
+                                    });
+                                    movables.insert(e, Movable);
+                                    self.state = Targeting(Aim::Throw(idx));
+                                    break;
                                 }
+                                (Inventory, Some((ItemKind::Consumable(_), _))) => Some(action::Action::Use { index: idx, target: None }),
+                                (Inventory, Some(_)) => Some(action::Action::Equip(idx)),
+                                (Equip, Some((_, Some(slot)))) => Some(action::Action::Unequip(slot)),
+                                _ => None,
+                            };
+
+                            if let Some(chosen) = chosen {
+                                self.act(chosen);
+                                // Show what's left once it's done
+                                self.inv_valid = false;
+                                break;
                             }
                         },
 
@@ -609,6 +668,46 @@ impl specs::System<()> for InputSystem {
                 self.inv_list.refresh(&mut inv.window);
                 self.render(&mut res.window);
             }
+
+            History => {
+                let (mut res, mut history) = arg.fetch(|world| {
+                    (
+                        world.write_resource::<ui::CommandPanelResource>(),
+                        world.write_resource::<ui::HistoryPanelResource>(),
+                    )
+                });
+                let page = history.window.height as usize - 1;
+
+                for event in self.inputs.try_iter() {
+                    let action = match event {
+                        Event::Key(key) => self.keymap.action(key),
+                        _ => None,
+                    };
+
+                    match action {
+                        Some(Action::Cancel) | Some(Action::MessageHistory) => {
+                            self.sub_screen.send(::screen::SubScreenEvent::Pop).unwrap();
+                            self.state = Toplevel;
+                            history.scroll = 0;
+                            break;
+                        }
+
+                        Some(Action::MoveUp) => history.scroll += 1,
+                        Some(Action::MoveDown) => history.scroll = history.scroll.saturating_sub(1),
+                        Some(Action::ScrollUp) => history.scroll += page,
+                        Some(Action::ScrollDown) => history.scroll = history.scroll.saturating_sub(page),
+
+                        Some(Action::SwitchList) => {
+                            history.filter = ui::MessageKind::next(history.filter);
+                            history.scroll = 0;
+                        }
+
+                        _ => {}
+                    }
+                }
+
+                self.render(&mut res.window);
+            }
         }
     }
 }
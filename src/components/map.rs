@@ -16,27 +16,73 @@
 use std::collections::VecDeque;
 use std::sync::mpsc;
 
-use rand::{self, Rng};
+use rand::Rng;
 use specs;
 use voodoo::compositor::Compositor;
 use voodoo::window::{Point, TermCell, Window};
 
+use ::events::{EventBus, GameEvent};
+use ::util::rng::Rngs;
 use super::camera::Camera;
 
-#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub enum MapCell {
     Null,
     Wall,
     Floor,
+    /// Leads down to the next level.
+    Stairs,
+    Door(DoorState),
+}
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum DoorState {
+    Open,
+    /// Blocks movement and sight until opened.
+    Closed,
+    /// Can't be opened, only broken down.
+    Locked,
+}
+
+/// Damage it takes to breach a wall.
+pub const WALL_DURABILITY: usize = 8;
+/// Damage it takes to break down a closed or locked door.
+pub const DOOR_DURABILITY: usize = 4;
+/// Chance out of 1000 that a room gets a door, and that such a door
+/// is locked.
+const DOOR_CHANCE: u32 = 500;
+const LOCKED_CHANCE: u32 = 150;
+
+/// How much the player knows about a cell.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum Visibility {
+    /// Never seen.
+    Unseen,
+    /// Seen before, but not currently in view.
+    Remembered,
+    /// Currently in view.
+    Visible,
 }
 
 pub struct Map {
     pub map: Vec<MapCell>,
     pub contents: Vec<Option<specs::Entity>>,
+    pub visibility: Vec<Visibility>,
+    /// Damage taken by each cell's terrain.
+    pub damage: Vec<usize>,
+    /// Bumped whenever terrain changes after generation, so that
+    /// anything worked out from the map (like a planned route) can
+    /// tell when it's stale.
+    pub revision: u64,
     pub width: usize,
     pub height: usize,
 }
 
+/// Where the player is in the dungeon.
+pub struct Dungeon {
+    pub depth: usize,
+}
+
 pub struct MapRender {
     window: Window,
 }
@@ -52,7 +98,8 @@ pub struct RenderSystem {
 
 pub struct BuilderSystem {
     can_create_entity: bool,
-    message_queue: mpsc::Sender<String>,
+    events: EventBus,
+    descend: mpsc::Receiver<()>,
 }
 
 impl Map {
@@ -60,6 +107,9 @@ impl Map {
         Map {
             map: vec![MapCell::Null; width * height],
             contents: vec![None; width * height],
+            visibility: vec![Visibility::Unseen; width * height],
+            damage: vec![0; width * height],
+            revision: 0,
             width: width,
             height: height,
         }
@@ -69,6 +119,8 @@ impl Map {
         let index = y * self.width + x;
         match (self.map.get(index), self.contents.get(index)) {
             (Some(&MapCell::Floor), Some(&None)) => true,
+            (Some(&MapCell::Stairs), Some(&None)) => true,
+            (Some(&MapCell::Door(DoorState::Open)), Some(&None)) => true,
             _ => false,
         }
     }
@@ -76,11 +128,94 @@ impl Map {
     pub fn occupable(&self, x: usize, y: usize) -> bool {
         let index = y * self.width + x;
         match self.map.get(index) {
-            Some(&MapCell::Floor) => true,
+            Some(&MapCell::Floor) | Some(&MapCell::Stairs) | Some(&MapCell::Door(DoorState::Open)) => true,
             _ => false,
         }
     }
 
+    /// Whether a diagonal step from one cell to a neighbouring one
+    /// would squeeze past a wall. Diagonal steps are only allowed
+    /// when both cells beside the step are open; entities don't
+    /// count, only terrain.
+    pub fn cuts_corner(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        if from.0 == to.0 || from.1 == to.1 {
+            return false;
+        }
+        !self.occupable(from.0, to.1) || !self.occupable(to.0, from.1)
+    }
+
+    /// Whether this cell blocks line of sight.
+    pub fn opaque(&self, x: usize, y: usize) -> bool {
+        let index = y * self.width + x;
+        match self.map.get(index) {
+            Some(&MapCell::Floor) | Some(&MapCell::Stairs) | Some(&MapCell::Door(DoorState::Open)) => false,
+            _ => true,
+        }
+    }
+
+    /// Whether nothing opaque lies between two cells. The cells
+    /// themselves don't count.
+    pub fn line_of_sight(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        let points = ::util::bresenham(super::position::Position::new(from.0, from.1),
+                                       super::position::Position::new(to.0, to.1));
+        points.iter()
+            .filter(|point| (point.x, point.y) != from && (point.x, point.y) != to)
+            .all(|point| !self.opaque(point.x, point.y))
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> MapCell {
+        let index = y * self.width + x;
+        self.map.get(index).cloned().unwrap_or(MapCell::Null)
+    }
+
+    /// Change the terrain of a cell during play.
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: MapCell) {
+        let index = y * self.width + x;
+        self.map[index] = cell;
+        self.damage[index] = 0;
+        self.revision += 1;
+    }
+
+    /// Open a closed door. Returns false if there's no closed door
+    /// here (locked doors stay shut).
+    pub fn open_door(&mut self, x: usize, y: usize) -> bool {
+        if self.cell(x, y) == MapCell::Door(DoorState::Closed) {
+            self.set_cell(x, y, MapCell::Door(DoorState::Open));
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    /// Damage the terrain of a cell, turning it into floor if that's
+    /// enough to break it. Returns whether it broke.
+    pub fn damage_terrain(&mut self, x: usize, y: usize, damage: usize) -> bool {
+        let index = y * self.width + x;
+        let durability = match self.cell(x, y).durability() {
+            Some(durability) => durability,
+            None => return false,
+        };
+
+        self.damage[index] += damage;
+        if self.damage[index] >= durability {
+            self.set_cell(x, y, MapCell::Floor);
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    pub fn visibility(&self, x: usize, y: usize) -> Visibility {
+        let index = y * self.width + x;
+        self.visibility.get(index).cloned().unwrap_or(Visibility::Unseen)
+    }
+
+    pub fn is_visible(&self, x: usize, y: usize) -> bool {
+        self.visibility(x, y) == Visibility::Visible
+    }
+
     pub fn contents(&self, x: usize, y: usize) -> Option<specs::Entity> {
         let index = y * self.width + x;
         self.contents[index]
@@ -99,6 +234,35 @@ impl Map {
     }
 }
 
+impl MapCell {
+    /// How much damage breaks this cell, if it can be broken.
+    pub fn durability(&self) -> Option<usize> {
+        match *self {
+            MapCell::Wall => Some(WALL_DURABILITY),
+            MapCell::Door(DoorState::Closed) | MapCell::Door(DoorState::Locked) => Some(DOOR_DURABILITY),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MapCell::Null => "nothing",
+            MapCell::Wall => "wall",
+            MapCell::Floor => "floor",
+            MapCell::Stairs => "stairs",
+            MapCell::Door(_) => "door",
+        }
+    }
+}
+
+impl Dungeon {
+    pub fn new() -> Dungeon {
+        Dungeon {
+            depth: 1,
+        }
+    }
+}
+
 impl MapRender {
     pub fn new(window: Window) -> MapRender {
         MapRender {
@@ -106,7 +270,10 @@ impl MapRender {
         }
     }
 
-    pub fn render(&mut self, map: &Map, camera: &Camera) {
+    /// Draw the part of the map in view of the camera. Unless
+    /// `reveal_all` is set, remembered cells are dimmed and unseen
+    /// cells are left blank.
+    pub fn render(&mut self, map: &Map, camera: &Camera, reveal_all: bool) {
         use self::MapCell::*;
 
         // TODO: use camera view (need Rect structs)
@@ -116,14 +283,25 @@ impl MapRender {
                 let offset = camera.position.x as usize + start + col_offset as usize;
                 let y = row_offset;
                 let x = col_offset;
-                self.window.put_at(
-                    Point::new(x, y),
-                    Into::<TermCell>::into((match map.map[offset] {
-                        Null => ' ',
-                        Wall => '#',
-                        Floor => '·',
-                    })).faint()
-                )
+                let tc = Into::<TermCell>::into(match map.map[offset] {
+                    Null => ' ',
+                    Wall => '#',
+                    Floor => '·',
+                    Stairs => '>',
+                    Door(DoorState::Open) => '\'',
+                    Door(_) => '+',
+                });
+                let tc = if reveal_all {
+                    tc.faint()
+                }
+                else {
+                    match map.visibility[offset] {
+                        Visibility::Visible => tc,
+                        Visibility::Remembered => tc.faint(),
+                        Visibility::Unseen => ' '.into(),
+                    }
+                };
+                self.window.put_at(Point::new(x, y), tc);
             }
         }
     }
@@ -146,7 +324,7 @@ impl MapBuilder {
         }
     }
 
-    pub fn dig_feature(&mut self, map: &mut Map) {
+    pub fn dig_feature<R: Rng>(&mut self, map: &mut Map, rng: &mut R) {
         // Based on http://www.roguebasin.com/index.php?title=Dungeon-Building_Algorithm
         use self::MapCell::*;
 
@@ -171,7 +349,7 @@ impl MapBuilder {
         else {
             use ::util::Direction;
             'testing: for _ in 0..1000 {
-                let index = rand::thread_rng().gen_range(0, self.actual_map.len());
+                let index = rng.gen_range(0, self.actual_map.len());
                 if let Wall = self.actual_map[index] {
                     let above = util::above(&self.actual_map, map.width, index)
                         .and_then(|i| self.actual_map.get(i)).cloned().unwrap_or(Floor);
@@ -202,18 +380,28 @@ impl MapBuilder {
                         _ => continue 'testing,
                     };
 
-                    let (width_range, length_range) = if rand::thread_rng().next_f32() < 0.6 {
+                    let (width_range, length_range, is_room) = if rng.next_f32() < 0.6 {
                         // Corridor
-                        ((3, 5), (5, 20))
+                        ((3, 5), (5, 20), false)
                     }
                     else {
                         // Room
-                        ((5, 20), (5, 20))
+                        ((5, 20), (5, 20), true)
                     };
 
                     let res = util::generate_room(index, direction, &self.actual_map, map.width,
-                                                  width_range, length_range);
-                    if let Some(cells) = res {
+                                                  width_range, length_range, rng);
+                    if let Some(mut cells) = res {
+                        // The first cell is the entrance
+                        if is_room && rng.gen_range(0, 1000) < DOOR_CHANCE {
+                            cells[0].1 = Door(if rng.gen_range(0, 1000) < LOCKED_CHANCE {
+                                DoorState::Locked
+                            }
+                            else {
+                                DoorState::Closed
+                            });
+                        }
+
                         for &(index, cell) in cells.iter() {
                             self.actual_map[index] = cell;
                         }
@@ -246,9 +434,10 @@ impl specs::System<()> for RenderSystem {
     fn run(&mut self, arg: specs::RunArg, _: ()) {
         use specs::Join;
 
-        let (map, mut renderers, mut cameras, focused, positions) = arg.fetch(|world| {
+        let (map, builders, mut renderers, mut cameras, focused, positions) = arg.fetch(|world| {
             (
                 world.read_resource::<Map>(),
+                world.read::<MapBuilder>(),
                 world.write::<MapRender>(),
                 world.write::<Camera>(),
                 world.read::<super::ui::Focus>(),
@@ -262,17 +451,21 @@ impl specs::System<()> for RenderSystem {
             }
         }
 
+        // Show the map being dug out, since there is nobody to see it yet
+        let reveal_all = (&builders).iter().next().is_some();
+
         for (renderer, camera) in (&mut renderers, &cameras).iter() {
-            renderer.render(&map, &camera);
+            renderer.render(&map, &camera, reveal_all);
         }
     }
 }
 
 impl BuilderSystem {
-    pub fn new(message_queue: mpsc::Sender<String>) -> BuilderSystem {
+    pub fn new(events: EventBus, descend: mpsc::Receiver<()>) -> BuilderSystem {
         BuilderSystem {
             can_create_entity: false,
-            message_queue: message_queue,
+            events: events,
+            descend: descend,
         }
     }
 }
@@ -280,54 +473,93 @@ impl BuilderSystem {
 impl specs::System<()> for BuilderSystem {
     fn run(&mut self, arg: specs::RunArg, _: ()) {
         use specs::Join;
-        use voodoo::color::ColorValue;
-        use voodoo::window::TermCell;
+
+        if let Ok(()) = self.descend.try_recv() {
+            let (mut map, mut dungeon, entities, players, positions, renderers, mut builders) = arg.fetch(|world| {
+                (
+                    world.write_resource::<Map>(),
+                    world.write_resource::<Dungeon>(),
+                    world.entities(),
+                    world.read::<super::player::Player>(),
+                    world.read::<super::position::Position>(),
+                    world.read::<MapRender>(),
+                    world.write::<MapBuilder>(),
+                )
+            });
+
+            // Throw away the old level, except for the player, who
+            // gets placed again once the new one is dug
+            let (width, height) = (map.width, map.height);
+            *map = Map::new(width, height);
+            dungeon.depth += 1;
+            for (entity, _, _) in (&entities, &positions, !&players).iter() {
+                arg.delete(entity);
+            }
+            // Keep the player's cell occupied until they are placed,
+            // since moving vacates it
+            for (entity, _, position) in (&entities, &players, &positions).iter() {
+                map.fill(entity, position.x, position.y);
+            }
+            for (entity, _) in (&entities, &renderers).iter() {
+                builders.insert(entity, MapBuilder::new());
+            }
+
+            self.events.publish(GameEvent::Descended { depth: dungeon.depth });
+            return;
+        }
 
         if self.can_create_entity {
-            self.message_queue.send("Placing player and enemies…".into()).unwrap();
+            self.events.publish(GameEvent::MapGenerated);
 
             let (
                 entities,
                 mut builders,
             ) = arg.fetch(|world| {
                 let mut map = world.write_resource::<Map>();
-
-                let mut equip = super::player::Equip::new();
-                equip.primary = Some(super::player::Item {
-                    name: "Xinhai Pistol".into(),
-                    kind: super::player::ItemKind::Weapon {
-                        damage: (1, 4),
-                        accuracy: 700,
-                        range: 5,
-                    },
-                    slot: Some(super::player::ItemSlot::Primary),
-                });
-
-                let entity = world.create_later_build()
+                let mut rngs = world.write_resource::<Rngs>();
+                let content = world.read_resource::<::content::Content>();
+                let depth = world.read_resource::<Dungeon>().depth;
+                // The player starts in the middle of the first room
+                let start = (map.width / 2, map.height / 2);
+
+                // Players coming down the stairs keep their health
+                // and equipment
+                let existing = {
+                    let entities = world.entities();
+                    let players = world.read::<super::player::Player>();
+                    let mut positions = world.write::<super::position::Position>();
+                    let existing = (&entities, &players, &mut positions).iter().next().map(|(entity, _, position)| {
+                        map.vacate(position.x, position.y);
+                        *position = super::position::Position::new(start.0, start.1);
+                        entity
+                    });
+                    existing
+                };
+
+                let player = &content.player;
+                let entity = existing.unwrap_or_else(|| world.create_later_build()
                     .with(super::input::Movable)
-                    .with(super::combat::DamageReduction { value: 0 })
+                    .with(super::combat::DamageReduction::new())
                     .with(super::player::Player::new())
-                    .with(super::player::Inventory::new())
-                    .with(equip)
-                    .with(super::input::Movable)
-                    .with(super::position::Position::new(50, 50))
-                    .with(super::drawable::StaticDrawable {
-                        tc: Into::<TermCell>::into('@').with_fg(ColorValue::Green)
-                    })
-                    .with(super::health::Health::new(10, 10))
+                    .with(player.inventory.clone())
+                    .with(player.equip.clone())
+                    .with(super::position::Position::new(start.0, start.1))
+                    .with(super::drawable::StaticDrawable::new(player.glyph))
+                    .with(super::health::Health::new(player.health, player.health))
+                    .with(super::turn::Actor::new(player.speed))
                     .with(super::ui::Focus)
-                    .build();
-                map.fill(entity, 50, 50);
+                    .build());
+                map.fill(entity, start.0, start.1);
 
-                for _ in 0..50 {
+                for _ in 0..(50 + 10 * (depth - 1)) {
                     for _ in 0..1000 {
-                        let index = rand::thread_rng().gen_range(0, map.map.len());
+                        let index = rngs.mapgen.gen_range(0, map.map.len());
                         if let MapCell::Floor = map.map[index] {
                             let y = index / map.width;
                             let x = index % map.width;
 
                             // Don't spawn them close to the player
-                            if (x as i32 - 50).pow(2) + (y as i32 - 50).pow(2) < 49 {
+                            if ::util::distance2((x, y), start) < 49 {
                                 continue;
                             }
 
@@ -336,63 +568,22 @@ impl specs::System<()> for BuilderSystem {
                                 continue;
                             }
 
-                            let mut equip = super::player::Equip::new();
-                            equip.primary = Some(super::player::Item {
-                                name: "Subduction Pistol".into(),
-                                kind: super::player::ItemKind::Weapon {
-                                    damage: (1, 2),
-                                    accuracy: 600,
-                                    range: 3,
-                                },
-                                slot: Some(super::player::ItemSlot::Primary),
-                            });
-
-                            let entity = world.create_later_build()
+                            let template = content.choose_enemy(&mut rngs.mapgen);
+                            let mut builder = world.create_later_build()
                                 .with(super::ai::ChaseBehavior::new())
-                                .with(equip)
-                                .with(super::player::DropsLoot::new(400, vec![
-                                    super::player::Item {
-                                        name: "Stun Baton".into(),
-                                        kind: super::player::ItemKind::Weapon {
-                                            damage: (3, 4),
-                                            accuracy: 800,
-                                            range: 0,
-                                        },
-                                        slot: Some(super::player::ItemSlot::Secondary),
-                                    },
-                                    super::player::Item {
-                                        name: "Sniper Rifle".into(),
-                                        kind: super::player::ItemKind::Weapon {
-                                            damage: (2, 7),
-                                            accuracy: 700,
-                                            range: 7,
-                                        },
-                                        slot: Some(super::player::ItemSlot::Primary),
-                                    },
-                                    super::player::Item {
-                                        name: "Shotgun".into(),
-                                        kind: super::player::ItemKind::Weapon {
-                                            damage: (3, 7),
-                                            accuracy: 600,
-                                            range: 1,
-                                        },
-                                        slot: Some(super::player::ItemSlot::Primary),
-                                    },
-                                    super::player::Item {
-                                        name: "Kevlar Vest".into(),
-                                        kind: super::player::ItemKind::Armor {
-                                            health: 2,
-                                            damage_reduction: 1,
-                                        },
-                                        slot: Some(super::player::ItemSlot::Body),
-                                    },
-                                ]))
+                                .with(super::ai::Behavior::new(template.archetype, (x, y)))
+                                .with(template.archetype.perception())
+                                .with(super::turn::Actor::new(template.speed))
+                                .with(template.equip.clone())
+                                .with(template.inventory.clone())
                                 .with(super::position::Position::new(x, y))
-                                .with(super::drawable::StaticDrawable {
-                                    tc: Into::<TermCell>::into('e').with_fg(ColorValue::Red),
-                                })
-                                .with(super::health::Health::new(3, 3))
-                                .build();
+                                .with(super::drawable::StaticDrawable::new(template.glyph))
+                                .with(super::health::Health::new(template.health + depth - 1,
+                                                                 template.health + depth - 1));
+                            if let Some(ref loot) = template.loot {
+                                builder = builder.with(loot.clone());
+                            }
+                            let entity = builder.build();
                             map.fill(entity, x, y);
                             break;
                         }
@@ -401,16 +592,15 @@ impl specs::System<()> for BuilderSystem {
 
                 for _ in 0..150 {
                     for _ in 0..1000 {
-                        let index = rand::thread_rng().gen_range(0, map.map.len());
+                        let index = rngs.mapgen.gen_range(0, map.map.len());
                         let y = index / map.width;
                         let x = index % map.width;
 
                         if map.passable(x, y) {
                             let entity = world.create_later_build()
                                 .with(super::position::Position::new(x, y))
-                                .with(super::drawable::StaticDrawable {
-                                    tc: Into::<TermCell>::into('▒').faint(),
-                                })
+                                .with(super::drawable::StaticDrawable::new(
+                                    super::drawable::Glyph::new('▒').faint()))
                                 .with(super::health::Health::new(1, 1))
                                 .with(super::health::Cover::new(-200))
                                 .build();
@@ -420,6 +610,35 @@ impl specs::System<()> for BuilderSystem {
                     }
                 }
 
+                // Put the stairs somewhere away from the start
+                let mut stairs = None;
+                for _ in 0..1000 {
+                    let index = rngs.mapgen.gen_range(0, map.map.len());
+                    let y = index / map.width;
+                    let x = index % map.width;
+
+                    if map.passable(x, y) && ::util::distance2((x, y), start) >= 400 {
+                        stairs = Some(index);
+                        break;
+                    }
+                }
+                // Failing that, as far as the player can walk from
+                // the start, so that every level has a way down
+                if stairs.is_none() {
+                    let walkable = |x: usize, y: usize| match map.cell(x, y) {
+                        MapCell::Floor | MapCell::Stairs | MapCell::Door(_) => Some(1),
+                        _ => None,
+                    };
+                    let reachable = ::util::path::flood(&map, start, map.map.len(), walkable);
+                    stairs = reachable.iter()
+                        .filter(|&(&(x, y), _)| map.passable(x, y))
+                        .max_by_key(|&(&(x, y), &cost)| (cost, y, x))
+                        .map(|(&(x, y), _)| y * map.width + x);
+                }
+                if let Some(index) = stairs {
+                    map.map[index] = MapCell::Stairs;
+                }
+
                 (
                     world.entities(),
                     world.write::<MapBuilder>(),
@@ -442,17 +661,17 @@ impl specs::System<()> for BuilderSystem {
             return;
         }
 
-        let (mut map, mut builders) = arg.fetch(|world| {
-            (world.write_resource::<Map>(), world.write::<MapBuilder>())
+        let (mut map, mut rngs, mut builders) = arg.fetch(|world| {
+            (world.write_resource::<Map>(), world.write_resource::<Rngs>(), world.write::<MapBuilder>())
         });
 
         for map_builder in (&mut builders).iter() {
             if map_builder.num_iterations == 0 {
-                self.message_queue.send("Generating map…".into()).unwrap();
+                self.events.publish(GameEvent::MapGenerating);
             }
 
             if map_builder.num_iterations < 100 {
-                map_builder.dig_feature(&mut map);
+                map_builder.dig_feature(&mut map, &mut rngs.mapgen);
             }
             else if map_builder.modified_cells.len() == 0 {
                 self.can_create_entity = true;
@@ -468,6 +687,8 @@ impl specs::System<()> for BuilderSystem {
 }
 
 mod util {
+    use rand::Rng;
+
     use ::util::Direction;
     use super::MapCell;
 
@@ -485,18 +706,37 @@ mod util {
             Direction::Left => {
                 left(map, width, cur)
             }
+            Direction::UpLeft => {
+                above(map, width, cur).and_then(|i| left(map, width, i))
+            }
+            Direction::UpRight => {
+                above(map, width, cur).and_then(|i| right(map, width, i))
+            }
+            Direction::DownLeft => {
+                below(map, width, cur).and_then(|i| left(map, width, i))
+            }
+            Direction::DownRight => {
+                below(map, width, cur).and_then(|i| right(map, width, i))
+            }
         }
     }
 
-    pub fn generate_room(start_point: usize, direction: Direction, actual_map: &[MapCell], map_width: usize,
-                         width_range: (usize, usize), height_range: (usize, usize)) -> Option<Vec<(usize, super::MapCell)>> {
-        use rand::{self, Rng};
+    /// Dig a room or corridor out from a wall. Rooms are axis-aligned,
+    /// so `direction` must be orthogonal; diagonal directions dig
+    /// nothing.
+    pub fn generate_room<R: Rng>(start_point: usize, direction: Direction, actual_map: &[MapCell], map_width: usize,
+                                 width_range: (usize, usize), height_range: (usize, usize),
+                                 rng: &mut R) -> Option<Vec<(usize, super::MapCell)>> {
         use super::MapCell::*;
 
-        let width = rand::thread_rng().gen_range(width_range.0, width_range.1);
-        let height = rand::thread_rng().gen_range(height_range.0, height_range.1);
+        if direction.is_diagonal() {
+            return None;
+        }
+
+        let width = rng.gen_range(width_range.0, width_range.1);
+        let height = rng.gen_range(height_range.0, height_range.1);
 
-        let offset = rand::thread_rng().gen_range(1, width - 1);
+        let offset = rng.gen_range(1, width - 1);
 
         let mut cells = vec![(start_point, Floor)];
 
@@ -523,6 +763,7 @@ mod util {
                         Direction::Right | Direction::Left => {
                             above(&actual_map, map_width, side_idx)
                         }
+                        _ => unreachable!(),
                     }.and_then(|idx| if actual_map[idx] != Null { None } else { Some(idx) }) {
                         cells.push((new_side_idx, if r == 0 || r == height - 1 || off == offset - 1 {
                             Wall
@@ -545,6 +786,7 @@ mod util {
                         Direction::Right | Direction::Left => {
                             below(&actual_map, map_width, side_idx)
                         }
+                        _ => unreachable!(),
                     }.and_then(|idx| if actual_map[idx] != Null { None } else { Some(idx) }) {
                         cells.push((new_side_idx, if r == 0 || r == height - 1 || off == width - 1 {
                             Wall
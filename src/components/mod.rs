@@ -15,6 +15,7 @@
 
 use specs::World;
 
+pub mod action;
 pub mod ai;
 pub mod camera;
 pub mod combat;
@@ -22,18 +23,26 @@ pub mod drawable;
 pub mod health;
 pub mod input;
 pub mod map;
+pub mod noise;
 pub mod player;
 pub mod position;
+pub mod status;
+pub mod turn;
 pub mod ui;
 
 pub fn register_all(world: &mut World) {
+    world.register::<ai::Behavior>();
     world.register::<ai::ChaseBehavior>();
     world.register::<ai::Dead>();
+    world.register::<ai::Perception>();
 
     world.register::<camera::Camera>();
 
-    world.register::<combat::Attack>();
+    world.register::<combat::Attacked>();
+    world.register::<combat::Crouching>();
     world.register::<combat::DamageReduction>();
+    world.register::<combat::Explosion>();
+    world.register::<combat::TemporaryDamageReduction>();
 
     world.register::<drawable::LineDrawable>();
     world.register::<drawable::StaticDrawable>();
@@ -53,7 +62,14 @@ pub fn register_all(world: &mut World) {
     world.register::<map::MapRender>();
     world.register::<map::MapBuilder>();
 
+    world.register::<noise::Noise>();
+    world.register::<noise::Sneaking>();
+
     world.register::<position::Position>();
 
+    world.register::<status::StatusEffects>();
+
+    world.register::<turn::Actor>();
+
     world.register::<ui::Focus>();
 }
@@ -0,0 +1,71 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Sound: what enemies hear, and how far.
+//!
+//! Actions make noise with a radius in cells. Sound spreads over the
+//! map like a path, losing a cell of radius per step and
+//! `WALL_ATTENUATION` for each wall or closed door it passes through,
+//! and alerts anyone it reaches to where it came from.
+
+use specs;
+
+use super::position::Position;
+
+/// Radius of the player's footsteps.
+pub const MOVE_NOISE: usize = 4;
+/// Radius of the player's footsteps while sneaking.
+pub const SNEAK_NOISE: usize = 1;
+/// Radius of opening a door.
+pub const DOOR_NOISE: usize = 3;
+/// Radius of kicking at a locked door.
+pub const KICK_NOISE: usize = 7;
+/// Radius of an explosion.
+pub const EXPLOSION_NOISE: usize = 12;
+/// Radius of cover being shot apart.
+pub const COVER_BREAK_NOISE: usize = 5;
+/// Radius lost passing through a wall or closed door.
+pub const WALL_ATTENUATION: usize = 4;
+
+/// A sound made this turn, heard once and then gone.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub struct Noise {
+    pub center: Position,
+    pub radius: usize,
+    /// Whoever made it, who doesn't need to go looking for it.
+    pub source: Option<specs::Entity>,
+}
+
+/// Moving slowly and quietly, until toggled off.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+pub struct Sneaking;
+
+impl Noise {
+    pub fn new(center: Position, radius: usize, source: Option<specs::Entity>) -> Noise {
+        Noise {
+            center: center,
+            radius: radius,
+            source: source,
+        }
+    }
+}
+
+impl specs::Component for Noise {
+    type Storage = specs::HashMapStorage<Noise>;
+}
+
+impl specs::Component for Sneaking {
+    type Storage = specs::NullStorage<Sneaking>;
+}
@@ -15,20 +15,99 @@
 
 use specs;
 
-#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+use super::combat::{DAMAGE_TYPES, DamageType, Resistances};
+use super::status::StatusEffect;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum AmmoType {
+    Pistol,
+    Rifle,
+    Shell,
+    Grenade,
+}
+
+/// The rounds loaded into a weapon.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct Magazine {
+    pub ammo: AmmoType,
+    pub capacity: usize,
+    pub loaded: usize,
+}
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub enum ItemKind {
     Weapon {
         damage: (usize, usize),
+        damage_type: DamageType,
         accuracy: usize,
+        /// Ignores this much of the target's damage reduction.
+        penetration: usize,
+        /// Out of 1000.
+        crit_chance: usize,
+        /// Damage on a critical hit, as a percentage of the roll.
+        crit_multiplier: usize,
         range: usize,
+        /// Energy spent attacking with this weapon.
+        cost: usize,
+        /// Weapons without a magazine never run out.
+        magazine: Option<Magazine>,
+        /// Shots explode, hitting everything within this distance of
+        /// where they land. Zero for ordinary weapons.
+        radius: usize,
+        /// Inflicted on whatever the weapon hits.
+        status: Option<StatusEffect>,
+        /// Can break through walls and doors.
+        breach: bool,
+        /// How far away, in cells, it can be heard.
+        noise: usize,
     },
     Armor {
         health: usize,
         damage_reduction: usize,
+        resistances: Resistances,
+    },
+    /// A stack of rounds for reloading.
+    Ammo {
+        ammo: AmmoType,
+        count: usize,
+    },
+    /// Used up when used.
+    Consumable(Effect),
+}
+
+/// What a consumable does.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum Effect {
+    /// Restore health, up to the maximum.
+    Heal(usize),
+    /// Extra damage reduction for a number of turns.
+    Shield {
+        damage_reduction: usize,
+        turns: usize,
+    },
+    /// Thrown, damaging everything within the radius of where it
+    /// lands.
+    Explode {
+        damage: (usize, usize),
+        radius: usize,
+        range: usize,
     },
 }
 
+/// What happened when trying to reload.
 #[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Reload {
+    /// The magazine now holds this many rounds.
+    Reloaded(usize),
+    /// The magazine is already full.
+    Full,
+    /// There are no rounds of the right type.
+    NoAmmo,
+    /// There's nothing equipped that takes ammo.
+    NotReloadable,
+}
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub enum ItemSlot {
     Primary,
     Secondary,
@@ -38,23 +117,23 @@ pub enum ItemSlot {
     Feet,
 }
 
-#[derive(Clone,Debug,Eq,PartialEq)]
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub struct Item {
     pub name: String,
     pub kind: ItemKind,
     pub slot: Option<ItemSlot>,
 }
 
-#[derive(Clone,Debug,Eq,PartialEq)]
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub struct Grabbable(pub Item);
 
-#[derive(Debug,Eq,PartialEq)]
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub struct DropsLoot {
     pub chance: usize,
     pub items: Vec<Item>,
 }
 
-#[derive(Clone,Debug,Default,Eq,PartialEq)]
+#[derive(Clone,Debug,Default,Eq,PartialEq,Serialize,Deserialize)]
 pub struct Equip {
     pub primary: Option<Item>,
     pub secondary: Option<Item>,
@@ -64,12 +143,13 @@ pub struct Equip {
     pub feet: Option<Item>,
 }
 
+#[derive(Clone,Debug,Default,Eq,PartialEq,Serialize,Deserialize)]
 pub struct Inventory {
     pub contents: Vec<Item>,
 }
 
 /// This entity is the player.
-#[derive(Clone,Debug,Default,Eq,PartialEq)]
+#[derive(Clone,Debug,Default,Eq,PartialEq,Serialize,Deserialize)]
 pub struct Player {
 }
 
@@ -78,22 +158,68 @@ impl ::ui::ListRenderable for Item {
         let mut result = vec![self.name.clone()];
 
         match self.kind {
-            ItemKind::Weapon { damage, accuracy, range } => {
-                result.push(format!("Damage: {} to {}", damage.0, damage.1 - 1));
+            ItemKind::Weapon {
+                damage, damage_type, accuracy, penetration, crit_chance, crit_multiplier,
+                range, cost, magazine, radius, status, breach, noise,
+            } => {
+                result.push(format!("Damage: {} to {} {}", damage.0, damage.1 - 1, damage_type.name()));
                 result.push(format!("Accuracy: {}/1000", accuracy));
+                if penetration > 0 {
+                    result.push(format!("Penetration: {}", penetration));
+                }
+                if crit_chance > 0 {
+                    result.push(format!("Crit: {}/1000, {}% damage", crit_chance, crit_multiplier));
+                }
                 if range > 0 {
                     result.push(format!("Range: {}", range));
                 }
                 else {
                     result.push("Range: Melee".into());
                 }
+                if radius > 0 {
+                    result.push(format!("Blast Radius: {}", radius));
+                }
+                if cost != ::components::turn::ATTACK_COST {
+                    result.push(format!("Attack Time: {}", cost));
+                }
+                if let Some(magazine) = magazine {
+                    result.push(format!("Ammo: {}/{} {}", magazine.loaded, magazine.capacity, magazine.ammo.name()));
+                }
+                if let Some(status) = status {
+                    result.push(format!("Inflicts: {}", status.describe()));
+                }
+                if breach {
+                    result.push("Breaches walls".into());
+                }
+                result.push(format!("Noise: {}", noise));
             }
-            ItemKind::Armor { health, damage_reduction } => {
+            ItemKind::Armor { health, damage_reduction, resistances } => {
                 if let Some(ref slot) = self.slot {
                     result.push(format!("{:?} Armor", slot));
                 }
                 result.push(format!("Health Bonus: {}", health));
                 result.push(format!("Damage Reduction: {}", damage_reduction));
+                for &damage_type in DAMAGE_TYPES.iter() {
+                    let resistance = resistances.get(damage_type);
+                    if resistance > 0 {
+                        result.push(format!("Resists {}: +{}", damage_type.name(), resistance));
+                    }
+                }
+            }
+            ItemKind::Ammo { ammo, count } => {
+                result.push(format!("{} {} rounds", count, ammo.name()));
+            }
+            ItemKind::Consumable(Effect::Heal(amount)) => {
+                result.push(format!("Heals: {}", amount));
+            }
+            ItemKind::Consumable(Effect::Shield { damage_reduction, turns }) => {
+                result.push(format!("Damage Reduction: +{}", damage_reduction));
+                result.push(format!("Duration: {} turns", turns));
+            }
+            ItemKind::Consumable(Effect::Explode { damage, radius, range }) => {
+                result.push(format!("Damage: {} to {}", damage.0, damage.1 - 1));
+                result.push(format!("Blast Radius: {}", radius));
+                result.push(format!("Range: {}", range));
             }
         }
 
@@ -101,6 +227,27 @@ impl ::ui::ListRenderable for Item {
     }
 }
 
+impl AmmoType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            AmmoType::Pistol => "pistol",
+            AmmoType::Rifle => "rifle",
+            AmmoType::Shell => "shell",
+            AmmoType::Grenade => "grenade",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AmmoType> {
+        match name {
+            "pistol" => Some(AmmoType::Pistol),
+            "rifle" => Some(AmmoType::Rifle),
+            "shell" => Some(AmmoType::Shell),
+            "grenade" => Some(AmmoType::Grenade),
+            _ => None,
+        }
+    }
+}
+
 impl Player {
     pub fn new() -> Player {
         Player {
@@ -197,6 +344,62 @@ impl Equip {
         }
     }
 
+    /// The energy it takes to attack with the primary weapon, or the
+    /// secondary one for melee.
+    pub fn attack_cost(&self, is_melee: bool) -> usize {
+        match *if is_melee { &self.secondary } else { &self.primary } {
+            Some(Item { kind: ItemKind::Weapon { cost, .. }, .. }) => cost,
+            _ => ::components::turn::ATTACK_COST,
+        }
+    }
+
+    /// The range of the primary weapon, or the secondary one for
+    /// melee. Zero for melee weapons, or if there's no weapon.
+    pub fn range(&self, is_melee: bool) -> usize {
+        match *if is_melee { &self.secondary } else { &self.primary } {
+            Some(Item { kind: ItemKind::Weapon { range, .. }, .. }) => range,
+            _ => 0,
+        }
+    }
+
+    /// How far away attacking with the primary weapon, or the
+    /// secondary one for melee, can be heard.
+    pub fn noise(&self, is_melee: bool) -> usize {
+        match *if is_melee { &self.secondary } else { &self.primary } {
+            Some(Item { kind: ItemKind::Weapon { noise, .. }, .. }) => noise,
+            _ => 0,
+        }
+    }
+
+    /// Whether the primary weapon has a round to fire (or doesn't
+    /// need any).
+    pub fn can_fire(&self) -> bool {
+        match self.primary {
+            Some(Item { kind: ItemKind::Weapon { magazine: Some(magazine), .. }, .. }) => magazine.loaded > 0,
+            Some(Item { kind: ItemKind::Weapon { .. }, .. }) => true,
+            _ => false,
+        }
+    }
+
+    /// Refill a magazine from ammo in the inventory: the primary
+    /// weapon's, or the secondary weapon's if the primary's can't be
+    /// refilled.
+    pub fn reload(&mut self, inventory: &mut Inventory) -> Reload {
+        match reload_weapon(&mut self.primary, inventory) {
+            Reload::Reloaded(loaded) => Reload::Reloaded(loaded),
+            primary => match reload_weapon(&mut self.secondary, inventory) {
+                Reload::NotReloadable => primary,
+                Reload::Full if primary != Reload::NotReloadable => primary,
+                secondary => secondary,
+            },
+        }
+    }
+
+    /// Whether reloading would load anything into either weapon.
+    pub fn can_reload(&self, inventory: &Inventory) -> bool {
+        can_reload_weapon(&self.primary, inventory) || can_reload_weapon(&self.secondary, inventory)
+    }
+
     pub fn list_equipped(&self) -> Vec<&Item> {
         let mut result = Vec::new();
         if let Some(ref i) = self.primary {
@@ -222,12 +425,88 @@ impl Equip {
     }
 }
 
+fn reload_weapon(weapon: &mut Option<Item>, inventory: &mut Inventory) -> Reload {
+    let magazine = match *weapon {
+        Some(Item { kind: ItemKind::Weapon { magazine: Some(ref mut magazine), .. }, .. }) => magazine,
+        _ => return Reload::NotReloadable,
+    };
+    if magazine.loaded == magazine.capacity {
+        return Reload::Full;
+    }
+
+    let wanted = magazine.capacity - magazine.loaded;
+    let taken = inventory.take_ammo(magazine.ammo, wanted);
+    if taken == 0 {
+        return Reload::NoAmmo;
+    }
+    magazine.loaded += taken;
+    Reload::Reloaded(magazine.loaded)
+}
+
+fn can_reload_weapon(weapon: &Option<Item>, inventory: &Inventory) -> bool {
+    match *weapon {
+        Some(Item { kind: ItemKind::Weapon { magazine: Some(magazine), .. }, .. }) => {
+            magazine.loaded < magazine.capacity && inventory.contents.iter().any(|item| match item.kind {
+                ItemKind::Ammo { ammo, count } => ammo == magazine.ammo && count > 0,
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
 impl Inventory {
     pub fn new() -> Inventory {
         Inventory {
             contents: Vec::new(),
         }
     }
+
+    /// Add an item, merging ammo into an existing stack of the same
+    /// type.
+    pub fn add(&mut self, item: Item) {
+        if let ItemKind::Ammo { ammo, count } = item.kind {
+            for existing in self.contents.iter_mut() {
+                if let ItemKind::Ammo { ammo: ref existing_ammo, count: ref mut existing_count } = existing.kind {
+                    if *existing_ammo == ammo {
+                        *existing_count += count;
+                        return;
+                    }
+                }
+            }
+        }
+        self.contents.push(item);
+    }
+
+    /// Remove the item at an index, if there is one.
+    pub fn take(&mut self, index: usize) -> Option<Item> {
+        if index < self.contents.len() {
+            Some(self.contents.remove(index))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Take up to `wanted` rounds of a type of ammo, removing stacks
+    /// that run out. Returns how many were taken.
+    pub fn take_ammo(&mut self, ammo: AmmoType, wanted: usize) -> usize {
+        let mut taken = 0;
+        for item in self.contents.iter_mut() {
+            if let ItemKind::Ammo { ammo: ref item_ammo, ref mut count } = item.kind {
+                if *item_ammo == ammo && taken < wanted {
+                    let amount = ::std::cmp::min(*count, wanted - taken);
+                    *count -= amount;
+                    taken += amount;
+                }
+            }
+        }
+        self.contents.retain(|item| match item.kind {
+            ItemKind::Ammo { count, .. } => count > 0,
+            _ => true,
+        });
+        taken
+    }
 }
 
 impl specs::Component for Grabbable {
@@ -16,7 +16,7 @@
 use specs;
 use voodoo;
 
-#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -65,6 +65,9 @@ impl super::input::OffsetMovable for Position {
         let new_x = if new_x < 0 { 0 } else { new_x as usize };
         let new_y = if new_y < 0 { 0 } else { new_y as usize };
 
+        if map.cuts_corner((self.x, self.y), (new_x, new_y)) {
+            return Err(());
+        }
         self.move_to(new_x, new_y, map)
     }
 }
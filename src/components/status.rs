@@ -0,0 +1,174 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Timed status effects, which tick at the start of their owner's
+//! turns.
+
+use specs;
+
+/// Most stacks of bleeding an entity can have.
+pub const MAX_BLEEDING_STACKS: usize = 3;
+/// Damage each turn from burning.
+pub const BURNING_DAMAGE: usize = 2;
+/// Accuracy lost while suppressed, out of 1000.
+pub const SUPPRESSED_PENALTY: i32 = 200;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum StatusKind {
+    /// One damage per stack each turn. Stacks.
+    Bleeding,
+    /// Loses every turn.
+    Stunned,
+    /// Takes `BURNING_DAMAGE` each turn.
+    Burning,
+    /// Less accurate.
+    Suppressed,
+}
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// How many more of its owner's turns it lasts.
+    pub turns: usize,
+    pub stacks: usize,
+}
+
+#[derive(Clone,Debug,Default,Eq,PartialEq,Serialize,Deserialize)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+}
+
+/// What an entity's status effects did at the start of its turn.
+pub struct Tick {
+    pub damage: usize,
+    /// The turn is lost.
+    pub stunned: bool,
+}
+
+impl StatusKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            StatusKind::Bleeding => "bleeding",
+            StatusKind::Stunned => "stunned",
+            StatusKind::Burning => "burning",
+            StatusKind::Suppressed => "suppressed",
+        }
+    }
+
+    /// A short name for the info panel.
+    pub fn abbreviation(&self) -> &'static str {
+        match *self {
+            StatusKind::Bleeding => "BLD",
+            StatusKind::Stunned => "STN",
+            StatusKind::Burning => "BRN",
+            StatusKind::Suppressed => "SUP",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StatusKind> {
+        match name {
+            "bleeding" => Some(StatusKind::Bleeding),
+            "stunned" => Some(StatusKind::Stunned),
+            "burning" => Some(StatusKind::Burning),
+            "suppressed" => Some(StatusKind::Suppressed),
+            _ => None,
+        }
+    }
+
+    pub fn max_stacks(&self) -> usize {
+        match *self {
+            StatusKind::Bleeding => MAX_BLEEDING_STACKS,
+            _ => 1,
+        }
+    }
+}
+
+impl StatusEffect {
+    pub fn new(kind: StatusKind, turns: usize) -> StatusEffect {
+        StatusEffect {
+            kind: kind,
+            turns: turns,
+            stacks: 1,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        if self.stacks > 1 {
+            format!("{} x{} ({} turns)", self.kind.name(), self.stacks, self.turns)
+        }
+        else {
+            format!("{} ({} turns)", self.kind.name(), self.turns)
+        }
+    }
+}
+
+impl StatusEffects {
+    pub fn new() -> StatusEffects {
+        Default::default()
+    }
+
+    /// Apply an effect. Reapplying an effect the entity already has
+    /// adds a stack, up to the kind's limit, and keeps whichever
+    /// duration is longer.
+    pub fn add(&mut self, effect: StatusEffect) {
+        for existing in self.effects.iter_mut() {
+            if existing.kind == effect.kind {
+                existing.stacks = ::std::cmp::min(existing.stacks + effect.stacks, effect.kind.max_stacks());
+                existing.turns = ::std::cmp::max(existing.turns, effect.turns);
+                return;
+            }
+        }
+        self.effects.push(effect);
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.effects.iter().any(|e| e.kind == kind)
+    }
+
+    pub fn accuracy_penalty(&self) -> i32 {
+        if self.has(StatusKind::Suppressed) {
+            SUPPRESSED_PENALTY
+        }
+        else {
+            0
+        }
+    }
+
+    /// Start a turn: work out what the effects do, then count them
+    /// down, removing those that run out.
+    pub fn tick(&mut self) -> Tick {
+        let mut tick = Tick {
+            damage: 0,
+            stunned: false,
+        };
+
+        for effect in self.effects.iter_mut() {
+            match effect.kind {
+                StatusKind::Bleeding => tick.damage += effect.stacks,
+                StatusKind::Burning => tick.damage += BURNING_DAMAGE,
+                StatusKind::Stunned => tick.stunned = true,
+                StatusKind::Suppressed => {}
+            }
+            effect.turns = effect.turns.saturating_sub(1);
+        }
+        self.effects.retain(|e| e.turns > 0);
+
+        tick
+    }
+}
+
+impl specs::Component for StatusEffects {
+    type Storage = specs::VecStorage<StatusEffects>;
+}
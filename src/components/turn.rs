@@ -0,0 +1,126 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Energy-based turn scheduling.
+//!
+//! Every actor gains energy equal to its speed each time step, and may
+//! act once it has at least `THRESHOLD` energy. Acting costs energy,
+//! so fast actors act more often and expensive actions (like firing a
+//! heavy weapon) leave an actor waiting longer for its next turn.
+
+use specs;
+
+/// Energy needed to act.
+pub const THRESHOLD: i32 = 100;
+/// Speed of an ordinary actor: one action costing `THRESHOLD` every
+/// ten time steps.
+pub const NORMAL_SPEED: usize = 10;
+
+pub const MOVE_COST: usize = 100;
+/// Cost of moving while sneaking.
+pub const SNEAK_COST: usize = 200;
+/// Extra cost of picking up an item while moving onto it.
+pub const PICK_UP_COST: usize = 50;
+pub const EQUIP_COST: usize = 100;
+pub const WAIT_COST: usize = 100;
+pub const RELOAD_COST: usize = 100;
+/// Cost of crouching down or standing up.
+pub const CROUCH_COST: usize = 50;
+/// Cost of using (or throwing) a consumable.
+pub const USE_COST: usize = 100;
+/// Cost of attacking with a weapon that doesn't specify one.
+pub const ATTACK_COST: usize = 100;
+
+/// Give up scheduling after this many AI actions without the player
+/// getting a turn, so a bad speed can't hang the game.
+pub const MAX_ACTIONS: usize = 10000;
+
+/// This entity takes turns.
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct Actor {
+    pub speed: usize,
+    pub energy: i32,
+}
+
+/// Decides who acts next.
+#[derive(Clone,Debug,Default,Eq,PartialEq,Serialize,Deserialize)]
+pub struct Scheduler {
+    /// How many turns the player has taken.
+    pub turns: u64,
+    /// How many time steps have passed.
+    pub time: u64,
+}
+
+impl Actor {
+    /// A new actor, ready to act immediately.
+    pub fn new(speed: usize) -> Actor {
+        Actor {
+            speed: speed,
+            energy: THRESHOLD,
+        }
+    }
+
+    pub fn spend(&mut self, cost: usize) {
+        self.energy -= cost as i32;
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            turns: 0,
+            time: 0,
+        }
+    }
+
+    /// Choose who acts next, advancing time until somebody has
+    /// enough energy. Whoever has the most energy goes first, and the
+    /// player (flagged by the third field) wins ties. Returns `None`
+    /// if nobody can ever act.
+    pub fn next(&mut self, actors: &mut [(specs::Entity, &mut Actor, bool)]) -> Option<specs::Entity> {
+        if actors.iter().all(|&(_, ref actor, _)| actor.speed == 0) {
+            return None;
+        }
+
+        loop {
+            let mut best: Option<(specs::Entity, i32, bool)> = None;
+            for &(entity, ref actor, is_player) in actors.iter() {
+                if actor.energy < THRESHOLD {
+                    continue;
+                }
+                let better = match best {
+                    None => true,
+                    Some((_, energy, _)) => actor.energy > energy || (actor.energy == energy && is_player),
+                };
+                if better {
+                    best = Some((entity, actor.energy, is_player));
+                }
+            }
+
+            if let Some((entity, _, _)) = best {
+                return Some(entity);
+            }
+
+            self.time += 1;
+            for &mut (_, ref mut actor, _) in actors.iter_mut() {
+                actor.energy += actor.speed as i32;
+            }
+        }
+    }
+}
+
+impl specs::Component for Actor {
+    type Storage = specs::VecStorage<Actor>;
+}
@@ -0,0 +1,540 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Item and enemy definitions, loaded from a TOML file at startup.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use rand::Rng;
+use toml;
+
+use ::components::ai::Archetype;
+use ::components::combat::{DamageType, Resistances};
+use ::components::drawable::Glyph;
+use ::components::player::{AmmoType, DropsLoot, Effect, Equip, Inventory, Item, ItemKind, ItemSlot, Magazine};
+use ::components::status::{StatusEffect, StatusKind};
+use ::components::turn;
+use ::ui::Color;
+
+pub const CONTENT_PATH: &'static str = "data/content.toml";
+/// Damage on a critical hit, as a percentage, for weapons that don't
+/// say.
+pub const DEFAULT_CRIT_MULTIPLIER: usize = 150;
+/// How far away, in cells, weapons that don't say can be heard.
+pub const DEFAULT_NOISE: usize = 8;
+/// As `DEFAULT_NOISE`, for melee weapons.
+pub const DEFAULT_MELEE_NOISE: usize = 2;
+
+#[derive(Debug)]
+pub enum ContentError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// A definition is well-formed TOML but doesn't make sense. The
+    /// first field says where (e.g. `items.shotgun`).
+    Invalid(String, String),
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContentError::Io(ref e) => write!(f, "{}", e),
+            ContentError::Parse(ref e) => write!(f, "{}", e),
+            ContentError::Invalid(ref location, ref message) => write!(f, "{}: {}", location, message),
+        }
+    }
+}
+
+impl From<io::Error> for ContentError {
+    fn from(e: io::Error) -> ContentError {
+        ContentError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ContentError {
+    fn from(e: toml::de::Error) -> ContentError {
+        ContentError::Parse(e)
+    }
+}
+
+fn invalid<T, S: Into<String>>(location: &str, message: S) -> Result<T, ContentError> {
+    Err(ContentError::Invalid(location.into(), message.into()))
+}
+
+#[derive(Debug,Deserialize)]
+struct RawContent {
+    player: RawPlayer,
+    items: BTreeMap<String, RawItem>,
+    enemies: BTreeMap<String, RawEnemy>,
+}
+
+#[derive(Debug,Deserialize)]
+struct RawPlayer {
+    glyph: String,
+    color: Option<String>,
+    health: usize,
+    speed: Option<usize>,
+    equip: Option<BTreeMap<String, String>>,
+    inventory: Option<Vec<String>>,
+}
+
+#[derive(Debug,Deserialize)]
+struct RawItem {
+    name: String,
+    kind: String,
+    slot: Option<String>,
+    // Weapons
+    damage: Option<Vec<usize>>,
+    damage_type: Option<String>,
+    accuracy: Option<usize>,
+    penetration: Option<usize>,
+    crit_chance: Option<usize>,
+    crit_multiplier: Option<f64>,
+    range: Option<usize>,
+    cost: Option<usize>,
+    magazine: Option<usize>,
+    status: Option<String>,
+    status_turns: Option<usize>,
+    breach: Option<bool>,
+    noise: Option<usize>,
+    // Weapons and ammo
+    ammo: Option<String>,
+    // Ammo
+    count: Option<usize>,
+    // Armor, and consumables that heal or shield
+    health: Option<usize>,
+    damage_reduction: Option<usize>,
+    // Armor
+    resistances: Option<BTreeMap<String, usize>>,
+    // Consumables
+    effect: Option<String>,
+    turns: Option<usize>,
+    // Weapons and explosive consumables
+    radius: Option<usize>,
+}
+
+#[derive(Debug,Deserialize)]
+struct RawEnemy {
+    glyph: String,
+    color: Option<String>,
+    health: usize,
+    speed: Option<usize>,
+    weight: usize,
+    archetype: Option<String>,
+    equip: Option<BTreeMap<String, String>>,
+    inventory: Option<Vec<String>>,
+    loot: Option<RawLoot>,
+}
+
+#[derive(Debug,Deserialize)]
+struct RawLoot {
+    chance: usize,
+    items: Vec<String>,
+}
+
+pub struct PlayerTemplate {
+    pub glyph: Glyph,
+    pub health: usize,
+    pub speed: usize,
+    pub equip: Equip,
+    pub inventory: Inventory,
+}
+
+pub struct EnemyTemplate {
+    pub glyph: Glyph,
+    pub health: usize,
+    pub speed: usize,
+    pub weight: usize,
+    pub archetype: Archetype,
+    pub equip: Equip,
+    pub inventory: Inventory,
+    pub loot: Option<DropsLoot>,
+}
+
+/// All the game's data-driven definitions.
+pub struct Content {
+    pub player: PlayerTemplate,
+    pub items: BTreeMap<String, Item>,
+    pub enemies: Vec<EnemyTemplate>,
+}
+
+fn parse_slot(location: &str, slot: &str) -> Result<ItemSlot, ContentError> {
+    match slot {
+        "primary" => Ok(ItemSlot::Primary),
+        "secondary" => Ok(ItemSlot::Secondary),
+        "head" => Ok(ItemSlot::Head),
+        "body" => Ok(ItemSlot::Body),
+        "legs" => Ok(ItemSlot::Legs),
+        "feet" => Ok(ItemSlot::Feet),
+        _ => invalid(location, format!("unknown slot \"{}\"", slot)),
+    }
+}
+
+fn parse_glyph(location: &str, glyph: &str, color: &Option<String>) -> Result<Glyph, ContentError> {
+    let mut chars = glyph.chars();
+    let ch = match (chars.next(), chars.next()) {
+        (Some(ch), None) => ch,
+        _ => return invalid(location, format!("glyph must be a single character, not \"{}\"", glyph)),
+    };
+
+    let mut result = Glyph::new(ch);
+    if let Some(ref color) = *color {
+        match Color::from_name(color) {
+            Some(color) => result = result.with_fg(color),
+            None => return invalid(location, format!("unknown color \"{}\"", color)),
+        }
+    }
+    Ok(result)
+}
+
+fn parse_ammo(location: &str, ammo: &Option<String>) -> Result<Option<AmmoType>, ContentError> {
+    match *ammo {
+        Some(ref ammo) => match AmmoType::from_name(ammo) {
+            Some(ammo) => Ok(Some(ammo)),
+            None => invalid(location, format!("unknown ammo type \"{}\"", ammo)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn parse_damage(location: &str, damage: &Option<Vec<usize>>) -> Result<(usize, usize), ContentError> {
+    match *damage {
+        Some(ref d) if d.len() == 2 && d[0] <= d[1] => Ok((d[0], d[1] + 1)),
+        Some(_) => invalid(location, "damage must be [min, max] with min <= max"),
+        None => invalid(location, "needs a damage range"),
+    }
+}
+
+fn parse_effect(location: &str, raw: &RawItem) -> Result<Effect, ContentError> {
+    match raw.effect.as_ref().map(|e| e.as_ref()) {
+        Some("heal") => match raw.health {
+            Some(health) if health > 0 => Ok(Effect::Heal(health)),
+            _ => invalid(location, "healing needs a positive health"),
+        },
+        Some("shield") => match (raw.damage_reduction, raw.turns) {
+            (Some(damage_reduction), Some(turns)) if damage_reduction > 0 && turns > 0 => Ok(Effect::Shield {
+                damage_reduction: damage_reduction,
+                turns: turns,
+            }),
+            _ => invalid(location, "shields need a positive damage_reduction and turns"),
+        },
+        Some("explode") => match (raw.radius, raw.range) {
+            (Some(radius), Some(range)) if range > 0 => Ok(Effect::Explode {
+                damage: parse_damage(location, &raw.damage)?,
+                radius: radius,
+                range: range,
+            }),
+            _ => invalid(location, "explosives need a radius and a positive range"),
+        },
+        Some(effect) => invalid(location, format!("unknown effect \"{}\"", effect)),
+        None => invalid(location, "consumables need an effect"),
+    }
+}
+
+/// Melee weapons hit blunt, and everything else ballistic, unless
+/// they say otherwise.
+fn parse_damage_type(location: &str, raw: &RawItem, melee: bool) -> Result<DamageType, ContentError> {
+    match raw.damage_type {
+        Some(ref name) => match DamageType::from_name(name) {
+            Some(damage_type) => Ok(damage_type),
+            None => invalid(location, format!("unknown damage type \"{}\"", name)),
+        },
+        None if melee => Ok(DamageType::Blunt),
+        None => Ok(DamageType::Ballistic),
+    }
+}
+
+/// Crit chance out of 1000, and the multiplier as a percentage.
+fn parse_crit(location: &str, raw: &RawItem) -> Result<(usize, usize), ContentError> {
+    let chance = match raw.crit_chance {
+        Some(chance) if chance <= 1000 => chance,
+        Some(_) => return invalid(location, "crit_chance must be at most 1000"),
+        None => 0,
+    };
+    let multiplier = match raw.crit_multiplier {
+        Some(multiplier) if multiplier >= 1.0 => (multiplier * 100.0).round() as usize,
+        Some(_) => return invalid(location, "crit_multiplier must be at least 1"),
+        None => DEFAULT_CRIT_MULTIPLIER,
+    };
+    Ok((chance, multiplier))
+}
+
+fn parse_resistances(location: &str, raw: &RawItem) -> Result<Resistances, ContentError> {
+    let mut resistances = Resistances::default();
+    if let Some(ref table) = raw.resistances {
+        for (name, &value) in table.iter() {
+            match DamageType::from_name(name) {
+                Some(DamageType::Ballistic) => resistances.ballistic = value,
+                Some(DamageType::Energy) => resistances.energy = value,
+                Some(DamageType::Blunt) => resistances.blunt = value,
+                None => return invalid(&format!("{}.resistances", location), format!("unknown damage type \"{}\"", name)),
+            }
+        }
+    }
+    Ok(resistances)
+}
+
+fn parse_status(location: &str, raw: &RawItem) -> Result<Option<StatusEffect>, ContentError> {
+    match (raw.status.as_ref(), raw.status_turns) {
+        (Some(status), Some(turns)) if turns > 0 => match StatusKind::from_name(status) {
+            Some(kind) => Ok(Some(StatusEffect::new(kind, turns))),
+            None => invalid(location, format!("unknown status \"{}\"", status)),
+        },
+        (None, None) => Ok(None),
+        _ => invalid(location, "a status needs a positive status_turns"),
+    }
+}
+
+fn parse_item(location: &str, raw: &RawItem) -> Result<Item, ContentError> {
+    let slot = match raw.slot {
+        Some(ref slot) => Some(parse_slot(location, slot)?),
+        None => None,
+    };
+    let ammo = parse_ammo(location, &raw.ammo)?;
+
+    let kind = match raw.kind.as_ref() {
+        "weapon" => {
+            let damage = parse_damage(location, &raw.damage)?;
+            let accuracy = match raw.accuracy {
+                Some(a) if a <= 1000 => a,
+                Some(_) => return invalid(location, "accuracy must be at most 1000"),
+                None => return invalid(location, "weapons need an accuracy"),
+            };
+            let cost = raw.cost.unwrap_or(turn::ATTACK_COST);
+            if cost == 0 {
+                return invalid(location, "cost must be positive");
+            }
+            if slot != Some(ItemSlot::Primary) && slot != Some(ItemSlot::Secondary) {
+                return invalid(location, "weapons go in the primary or secondary slot");
+            }
+            // Weapons start out fully loaded
+            let magazine = match (ammo, raw.magazine) {
+                (Some(ammo), Some(capacity)) if capacity > 0 => Some(Magazine {
+                    ammo: ammo,
+                    capacity: capacity,
+                    loaded: capacity,
+                }),
+                (None, None) => None,
+                _ => return invalid(location, "weapons that take ammo need both an ammo type and a positive magazine size"),
+            };
+            let range = raw.range.unwrap_or(0);
+            let radius = raw.radius.unwrap_or(0);
+            if range == 0 && radius > 0 {
+                return invalid(location, "melee weapons can't have a blast radius");
+            }
+            let (crit_chance, crit_multiplier) = parse_crit(location, raw)?;
+
+            ItemKind::Weapon {
+                damage: damage,
+                damage_type: parse_damage_type(location, raw, range == 0)?,
+                accuracy: accuracy,
+                penetration: raw.penetration.unwrap_or(0),
+                crit_chance: crit_chance,
+                crit_multiplier: crit_multiplier,
+                range: range,
+                cost: cost,
+                magazine: magazine,
+                radius: radius,
+                status: parse_status(location, raw)?,
+                breach: raw.breach.unwrap_or(false),
+                noise: raw.noise.unwrap_or(if range == 0 { DEFAULT_MELEE_NOISE } else { DEFAULT_NOISE }),
+            }
+        }
+        "armor" => {
+            match slot {
+                None => return invalid(location, "armor needs a slot"),
+                Some(ItemSlot::Primary) | Some(ItemSlot::Secondary) =>
+                    return invalid(location, "armor can't go in a weapon slot"),
+                _ => {}
+            }
+
+            ItemKind::Armor {
+                health: raw.health.unwrap_or(0),
+                damage_reduction: raw.damage_reduction.unwrap_or(0),
+                resistances: parse_resistances(location, raw)?,
+            }
+        }
+        "ammo" => {
+            if slot.is_some() {
+                return invalid(location, "ammo can't be equipped");
+            }
+
+            match (ammo, raw.count) {
+                (Some(ammo), Some(count)) if count > 0 => ItemKind::Ammo {
+                    ammo: ammo,
+                    count: count,
+                },
+                _ => return invalid(location, "ammo needs an ammo type and a positive count"),
+            }
+        }
+        "consumable" => {
+            if slot.is_some() {
+                return invalid(location, "consumables can't be equipped");
+            }
+
+            ItemKind::Consumable(parse_effect(location, raw)?)
+        }
+        kind => return invalid(location, format!("unknown item kind \"{}\"", kind)),
+    };
+
+    Ok(Item {
+        name: raw.name.clone(),
+        kind: kind,
+        slot: slot,
+    })
+}
+
+fn lookup_item(location: &str, items: &BTreeMap<String, Item>, id: &str) -> Result<Item, ContentError> {
+    match items.get(id) {
+        Some(item) => Ok(item.clone()),
+        None => invalid(location, format!("no item named \"{}\"", id)),
+    }
+}
+
+fn parse_speed(location: &str, speed: Option<usize>) -> Result<usize, ContentError> {
+    match speed {
+        Some(0) => invalid(location, "speed must be positive"),
+        Some(speed) => Ok(speed),
+        None => Ok(turn::NORMAL_SPEED),
+    }
+}
+
+fn parse_inventory(location: &str, items: &BTreeMap<String, Item>,
+                   inventory: &Option<Vec<String>>) -> Result<Inventory, ContentError> {
+    let mut result = Inventory::new();
+    if let Some(ref inventory) = *inventory {
+        let location = format!("{}.inventory", location);
+        for id in inventory.iter() {
+            result.add(lookup_item(&location, items, id)?);
+        }
+    }
+    Ok(result)
+}
+
+fn parse_equip(location: &str, items: &BTreeMap<String, Item>,
+               equip: &Option<BTreeMap<String, String>>) -> Result<Equip, ContentError> {
+    let mut result = Equip::new();
+    if let Some(ref equip) = *equip {
+        for (slot, id) in equip.iter() {
+            let slot_location = format!("{}.equip.{}", location, slot);
+            let slot = parse_slot(&slot_location, slot)?;
+            let item = lookup_item(&slot_location, items, id)?;
+            if item.slot != Some(slot) {
+                return invalid(&slot_location, format!("\"{}\" doesn't go in this slot", id));
+            }
+            result.equip(item);
+        }
+    }
+    Ok(result)
+}
+
+impl Content {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Content, ContentError> {
+        let mut source = String::new();
+        File::open(path)?.read_to_string(&mut source)?;
+        Content::parse(&source)
+    }
+
+    pub fn parse(source: &str) -> Result<Content, ContentError> {
+        let raw: RawContent = toml::from_str(source)?;
+
+        let mut items = BTreeMap::new();
+        for (id, item) in raw.items.iter() {
+            items.insert(id.clone(), parse_item(&format!("items.{}", id), item)?);
+        }
+
+        if raw.player.health == 0 {
+            return invalid("player", "health must be positive");
+        }
+        let player = PlayerTemplate {
+            glyph: parse_glyph("player", &raw.player.glyph, &raw.player.color)?,
+            health: raw.player.health,
+            speed: parse_speed("player", raw.player.speed)?,
+            equip: parse_equip("player", &items, &raw.player.equip)?,
+            inventory: parse_inventory("player", &items, &raw.player.inventory)?,
+        };
+
+        let mut enemies = Vec::new();
+        for (id, enemy) in raw.enemies.iter() {
+            let location = format!("enemies.{}", id);
+            if enemy.health == 0 {
+                return invalid(&location, "health must be positive");
+            }
+            if enemy.weight == 0 {
+                return invalid(&location, "weight must be positive");
+            }
+
+            let loot = if let Some(ref loot) = enemy.loot {
+                let loot_location = format!("{}.loot", location);
+                if loot.chance > 1000 {
+                    return invalid(&loot_location, "chance must be at most 1000");
+                }
+                let mut loot_items = Vec::new();
+                for id in loot.items.iter() {
+                    loot_items.push(lookup_item(&loot_location, &items, id)?);
+                }
+                Some(DropsLoot::new(loot.chance, loot_items))
+            }
+            else {
+                None
+            };
+
+            let archetype = match enemy.archetype {
+                Some(ref name) => match Archetype::from_name(name) {
+                    Some(archetype) => archetype,
+                    None => return invalid(&location, format!("unknown archetype \"{}\"", name)),
+                },
+                None => Archetype::Grunt,
+            };
+
+            enemies.push(EnemyTemplate {
+                glyph: parse_glyph(&location, &enemy.glyph, &enemy.color)?,
+                health: enemy.health,
+                speed: parse_speed(&location, enemy.speed)?,
+                weight: enemy.weight,
+                archetype: archetype,
+                equip: parse_equip(&location, &items, &enemy.equip)?,
+                inventory: parse_inventory(&location, &items, &enemy.inventory)?,
+                loot: loot,
+            });
+        }
+
+        if enemies.is_empty() {
+            return invalid("enemies", "at least one enemy must be defined");
+        }
+
+        Ok(Content {
+            player: player,
+            items: items,
+            enemies: enemies,
+        })
+    }
+
+    /// Choose an enemy to spawn, according to the spawn weights.
+    pub fn choose_enemy<R: Rng>(&self, rng: &mut R) -> &EnemyTemplate {
+        let total: usize = self.enemies.iter().map(|e| e.weight).sum();
+        let mut choice = rng.gen_range(0, total);
+        for enemy in self.enemies.iter() {
+            if choice < enemy.weight {
+                return enemy;
+            }
+            choice -= enemy.weight;
+        }
+        unreachable!()
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! What happens in the game, for whoever wants to know.
+//!
+//! Systems publish `GameEvent`s on the `EventBus` as things happen,
+//! and anything interested subscribes to get its own copy of every
+//! event published after that. The message log is one subscriber,
+//! turning events into text.
+
+use std::sync::{mpsc, Arc, Mutex};
+
+use specs;
+
+use ::components::combat::DamageType;
+use ::components::position::Position;
+use ::components::player::Reload;
+use ::components::status::StatusKind;
+
+/// Who did something, or had something done to them.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Who {
+    Player,
+    Enemy,
+    /// Anything else, like cover.
+    Object,
+}
+
+/// How an attack turned out.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum AttackOutcome {
+    NothingEquipped,
+    Miss,
+    HitNothing,
+    /// Hit a wall or door, breaking through it if `breached`.
+    HitTerrain { name: &'static str, breached: bool },
+    HitEntity(Position),
+    /// A melee weapon was used on something out of reach.
+    OutOfRange,
+    OutOfAmmo,
+    /// Aimed at their own cell.
+    NoTarget,
+    /// Fired something that explodes where it lands.
+    Explosive(Position),
+}
+
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum GameEvent {
+    /// Someone attacked; any damage follows as `Damaged`.
+    AttackResolved { who: Who, outcome: AttackOutcome },
+    /// Something took damage from an attack or explosion.
+    Damaged {
+        entity: specs::Entity,
+        critical: bool,
+        rolled: usize,
+        damage_type: DamageType,
+        absorbed: usize,
+        /// Health left, zero if it was killed.
+        left: usize,
+    },
+    /// Something was killed or destroyed.
+    EntityKilled { entity: specs::Entity, who: Who, position: Position },
+    /// Something took damage from a status effect.
+    StatusDamage { who: Who, damage: usize, left: usize },
+    StatusApplied { who: Who, kind: StatusKind },
+    /// Someone lost their turn to being stunned.
+    Stunned { who: Who },
+    ShieldExpired,
+    Thrown { item: String },
+    Exploded { center: Position, cover_destroyed: usize, breached: bool },
+
+    ItemPicked { who: Who, item: String },
+    /// The player equipped an item, leaving them with this much
+    /// damage reduction.
+    ItemEquipped { item: String, damage_reduction: usize },
+    CannotEquip { item: String },
+    Healed { amount: usize },
+    Shielded { damage_reduction: usize, turns: usize },
+    Reloaded { who: Who, loaded: usize },
+    /// The player tried to reload and couldn't.
+    ReloadFailed(Reload),
+    Crouched(bool),
+    Sneaking(bool),
+
+    DoorOpened { who: Who },
+    /// Someone kicked a locked door, breaking it if `broken`.
+    DoorKicked { who: Who, broken: bool },
+
+    /// An enemy noticed the player.
+    Spotted,
+    CalledForHelp,
+
+    MapGenerating,
+    MapGenerated,
+    Descended { depth: usize },
+
+    /// Something for the player's information only, e.g. the
+    /// description of what they're examining.
+    Notice(String),
+}
+
+/// Hands out published events to every subscriber. Cloning it gives
+/// another handle on the same bus.
+#[derive(Clone)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<GameEvent>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus {
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Receive every event published from now on.
+    pub fn subscribe(&self) -> mpsc::Receiver<GameEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, event: GameEvent) {
+        // Forget about subscribers that have gone away
+        self.subscribers.lock().unwrap().retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    pub fn notice<S: Into<String>>(&self, message: S) {
+        self.publish(GameEvent::Notice(message.into()));
+    }
+}
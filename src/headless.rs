@@ -0,0 +1,268 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Run the game without a terminal, for tests and batch jobs.
+
+use std::sync::mpsc;
+
+use specs::{self, Join};
+
+use ::components::{map, player, turn};
+use ::components::input::Event;
+use ::keymap::parse_key;
+use ::screen::{GameScreen, Screen, StateTransition};
+
+/// Give up waiting for the level to be generated after this many ticks.
+const MAX_GENERATION_TICKS: usize = 1000;
+/// Ticks to wait for an event that doesn't take a turn (e.g. opening
+/// the inventory) to be processed.
+const IDLE_TICKS: usize = 4;
+/// Ticks to wait for a turn to resolve before giving up.
+const MAX_TURN_TICKS: usize = 20;
+/// Ticks to let combat and death settle after a turn.
+const SETTLE_TICKS: usize = 2;
+
+pub struct Headless {
+    planner: specs::Planner<()>,
+    screen: GameScreen,
+    transitions: mpsc::Receiver<StateTransition>,
+    /// The screen the game asked to switch to (game over or quit), if any.
+    pub finished: Option<StateTransition>,
+    pub ticks: u64,
+}
+
+impl Headless {
+    /// Set up a game screen on the given planner, whose world should
+    /// already have its resources (RNG, content) added.
+    pub fn new(mut planner: specs::Planner<()>) -> Headless {
+        let (tx, rx) = mpsc::channel();
+        let screen = GameScreen::setup(&mut planner, tx);
+        Headless {
+            planner: planner,
+            screen: screen,
+            transitions: rx,
+            finished: None,
+            ticks: 0,
+        }
+    }
+
+    pub fn world(&mut self) -> &mut specs::World {
+        self.planner.wait();
+        self.planner.mut_world()
+    }
+
+    /// Run every system once.
+    pub fn tick(&mut self) {
+        self.planner.dispatch(());
+        self.planner.wait();
+        self.ticks += 1;
+
+        if let Some(transition) = self.transitions.try_iter().last() {
+            self.finished = Some(transition);
+        }
+    }
+
+    fn turns(&mut self) -> u64 {
+        self.world().read_resource::<turn::Scheduler>().turns
+    }
+
+    /// Tick until the level has been dug and populated. Returns false
+    /// if it took too long.
+    pub fn generate_level(&mut self) -> bool {
+        for _ in 0..MAX_GENERATION_TICKS {
+            self.tick();
+
+            let world = self.world();
+            let digging = (&world.read::<map::MapBuilder>()).iter().next().is_some();
+            let has_player = (&world.read::<player::Player>()).iter().next().is_some();
+            if !digging && has_player {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Send an event, then tick until the turn it started resolves
+    /// (or, if it doesn't take a turn, until it has been processed).
+    pub fn step(&mut self, event: Event) {
+        let before = self.turns();
+        self.screen.send(event);
+
+        let mut idle = 0;
+        for _ in 0..MAX_TURN_TICKS {
+            self.tick();
+            if self.finished.is_some() {
+                return;
+            }
+            if self.turns() > before {
+                break;
+            }
+            idle += 1;
+            if idle >= IDLE_TICKS && self.turns() == before {
+                break;
+            }
+        }
+
+        for _ in 0..SETTLE_TICKS {
+            self.tick();
+        }
+    }
+
+    /// Play a sequence of events, stopping early if the game ends.
+    pub fn run(&mut self, events: &[Event]) {
+        for event in events {
+            if self.finished.is_some() {
+                break;
+            }
+            self.step(*event);
+        }
+    }
+}
+
+/// Parse a script of key presses: whitespace-separated key names as
+/// understood by `keymap::parse_key`. Lines starting with `#` are
+/// comments.
+pub fn parse_script(script: &str) -> Result<Vec<Event>, String> {
+    let mut events = Vec::new();
+
+    for line in script.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+
+        for token in line.split_whitespace() {
+            events.push(Event::Key(match parse_key(token) {
+                Some(key) => key,
+                None => return Err(format!("unknown key \"{}\"", token)),
+            }));
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{self, Join};
+    use termion::event::Key;
+
+    use ::components::{health, map, player, position};
+    use ::components::input::Event;
+    use ::content::Content;
+    use ::keymap::Keymap;
+    use super::Headless;
+
+    /// Generate a level, then clear out everything around the player,
+    /// so that the tests don't depend on what was generated there.
+    /// Returns where the player is.
+    fn start() -> (Headless, usize, usize) {
+        let content = Content::load(concat!(env!("CARGO_MANIFEST_DIR"), "/data/content.toml")).unwrap();
+        let mut game = Headless::new(::make_planner(1, content, Keymap::default()));
+        assert!(game.generate_level());
+
+        let (x, y) = {
+            let world = game.world();
+            let (others, x, y) = {
+                let entities = world.entities();
+                let players = world.read::<player::Player>();
+                let positions = world.read::<position::Position>();
+                let others: Vec<_> = (&entities, &positions, !&players).iter()
+                    .map(|(entity, position, _)| (entity, *position))
+                    .collect();
+                let (_, here) = (&players, &positions).iter().next().unwrap();
+                (others, here.x, here.y)
+            };
+
+            {
+                let mut map = world.write_resource::<map::Map>();
+                for &(_, position) in others.iter() {
+                    map.vacate(position.x, position.y);
+                }
+                for cy in y - 2..y + 3 {
+                    for cx in x - 2..x + 3 {
+                        map.set_cell(cx, cy, map::MapCell::Floor);
+                    }
+                }
+            }
+            for (entity, _) in others {
+                world.delete_now(entity);
+            }
+            (x, y)
+        };
+
+        (game, x, y)
+    }
+
+    /// Put something down next to the player.
+    fn place<C: specs::Component>(game: &mut Headless, x: usize, y: usize, component: C) -> specs::Entity {
+        let world = game.world();
+        let entity = world.create_now()
+            .with(position::Position::new(x, y))
+            .with(component)
+            .build();
+        world.write_resource::<map::Map>().fill(entity, x, y);
+        entity
+    }
+
+    fn player_position(game: &mut Headless) -> (usize, usize) {
+        let world = game.world();
+        let players = world.read::<player::Player>();
+        let positions = world.read::<position::Position>();
+        let (_, here) = (&players, &positions).iter().next().unwrap();
+        (here.x, here.y)
+    }
+
+    #[test]
+    fn move_right() {
+        let (mut game, x, y) = start();
+        game.run(&[Event::Key(Key::Char('d'))]);
+        assert_eq!(player_position(&mut game), (x + 1, y));
+    }
+
+    #[test]
+    fn shoot_adjacent() {
+        let (mut game, x, y) = start();
+        let target = place(&mut game, x + 1, y, health::Health::new(100, 100));
+
+        // The pistol can miss, but not every time
+        let aim = [Event::Key(Key::Char('2')), Event::Key(Key::Char('d')), Event::Key(Key::Char(' '))];
+        for _ in 0..5 {
+            game.run(&aim);
+            if game.world().read::<health::Health>().get(target).unwrap().health < 100 {
+                break;
+            }
+        }
+        assert!(game.world().read::<health::Health>().get(target).unwrap().health < 100);
+        assert_eq!(player_position(&mut game), (x, y));
+    }
+
+    #[test]
+    fn pick_up_by_walking_over() {
+        let (mut game, x, y) = start();
+        let item = game.world().read_resource::<Content>().items["medkit"].clone();
+        place(&mut game, x + 1, y, player::Grabbable(item.clone()));
+
+        game.run(&[Event::Key(Key::Char('d'))]);
+        assert_eq!(player_position(&mut game), (x + 1, y));
+
+        let world = game.world();
+        let players = world.read::<player::Player>();
+        let inventories = world.read::<player::Inventory>();
+        let (_, inventory) = (&players, &inventories).iter().next().unwrap();
+        assert_eq!(inventory.contents.iter().filter(|i| **i == item).count(), 2);
+        assert!((&world.read::<player::Grabbable>()).iter().next().is_none());
+    }
+}
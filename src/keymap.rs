@@ -0,0 +1,399 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Key bindings, loaded from a TOML file at startup.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use termion::event::Key;
+use toml;
+
+use ::util::Direction;
+
+pub const KEYMAP_PATH: &'static str = "data/keys.toml";
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    /// Spend a turn doing nothing.
+    Wait,
+    Examine,
+    FirePrimary,
+    FireSecondary,
+    Reload,
+    /// Crouch down, or stand up again.
+    Crouch,
+    /// Start or stop moving slowly and quietly.
+    Sneak,
+    OpenInventory,
+    /// Switch between the inventory and the loadout.
+    SwitchList,
+    SaveAndQuit,
+    Descend,
+    /// Scroll the messages back a page.
+    ScrollUp,
+    /// Scroll the messages forward a page.
+    ScrollDown,
+    /// Show every message so far.
+    MessageHistory,
+    Confirm,
+    Cancel,
+}
+
+pub const ACTIONS: [(Action, &'static str); 24] = [
+    (Action::MoveUp, "move_up"),
+    (Action::MoveDown, "move_down"),
+    (Action::MoveLeft, "move_left"),
+    (Action::MoveRight, "move_right"),
+    (Action::MoveUpLeft, "move_up_left"),
+    (Action::MoveUpRight, "move_up_right"),
+    (Action::MoveDownLeft, "move_down_left"),
+    (Action::MoveDownRight, "move_down_right"),
+    (Action::Wait, "wait"),
+    (Action::Examine, "examine"),
+    (Action::FirePrimary, "fire_primary"),
+    (Action::FireSecondary, "fire_secondary"),
+    (Action::Reload, "reload"),
+    (Action::Crouch, "crouch"),
+    (Action::Sneak, "sneak"),
+    (Action::OpenInventory, "open_inventory"),
+    (Action::SwitchList, "switch_list"),
+    (Action::SaveAndQuit, "save_and_quit"),
+    (Action::Descend, "descend"),
+    (Action::ScrollUp, "scroll_up"),
+    (Action::ScrollDown, "scroll_down"),
+    (Action::MessageHistory, "message_history"),
+    (Action::Confirm, "confirm"),
+    (Action::Cancel, "cancel"),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|&&(_, n)| n == name).map(|&(action, _)| action)
+    }
+
+    /// The name used for the action in the key bindings file.
+    pub fn name(&self) -> &'static str {
+        ACTIONS.iter().find(|&&(a, _)| a == *self).map(|&(_, name)| name).unwrap()
+    }
+
+    /// The direction a movement action moves in.
+    pub fn direction(&self) -> Option<Direction> {
+        match *self {
+            Action::MoveUp => Some(Direction::Up),
+            Action::MoveDown => Some(Direction::Down),
+            Action::MoveLeft => Some(Direction::Left),
+            Action::MoveRight => Some(Direction::Right),
+            Action::MoveUpLeft => Some(Direction::UpLeft),
+            Action::MoveUpRight => Some(Direction::UpRight),
+            Action::MoveDownLeft => Some(Direction::DownLeft),
+            Action::MoveDownRight => Some(Direction::DownRight),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeymapError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// The file is well-formed TOML but doesn't make sense. The first
+    /// field says where (e.g. `bindings.move_up`).
+    Invalid(String, String),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeymapError::Io(ref e) => write!(f, "{}", e),
+            KeymapError::Parse(ref e) => write!(f, "{}", e),
+            KeymapError::Invalid(ref location, ref message) => write!(f, "{}: {}", location, message),
+        }
+    }
+}
+
+impl From<io::Error> for KeymapError {
+    fn from(e: io::Error) -> KeymapError {
+        KeymapError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for KeymapError {
+    fn from(e: toml::de::Error) -> KeymapError {
+        KeymapError::Parse(e)
+    }
+}
+
+#[derive(Debug,Deserialize)]
+struct RawKeymap {
+    preset: Option<String>,
+    bindings: Option<BTreeMap<String, Vec<String>>>,
+}
+
+/// Which keys trigger which actions. A key triggers at most one
+/// action; an action may have several keys.
+#[derive(Clone,Debug)]
+pub struct Keymap {
+    bindings: Vec<(Key, Action)>,
+}
+
+pub const PRESETS: [&'static str; 4] = ["default", "arrows", "vi", "numpad"];
+
+impl Keymap {
+    /// The bindings shared by the default, arrow key and vi presets.
+    fn common() -> Keymap {
+        let mut keymap = Keymap {
+            bindings: Vec::new(),
+        };
+        keymap.bind(Action::Wait, &[Key::Char('.')]);
+        keymap.bind(Action::Examine, &[Key::Char('1')]);
+        keymap.bind(Action::FirePrimary, &[Key::Char('2')]);
+        keymap.bind(Action::FireSecondary, &[Key::Char('3')]);
+        keymap.bind(Action::Reload, &[Key::Char('r')]);
+        keymap.bind(Action::Crouch, &[Key::Char('x')]);
+        keymap.bind(Action::Sneak, &[Key::Char('v')]);
+        keymap.bind(Action::OpenInventory, &[Key::Char('i')]);
+        keymap.bind(Action::SwitchList, &[Key::Char('\t')]);
+        keymap.bind(Action::SaveAndQuit, &[Key::Char('S')]);
+        keymap.bind(Action::Descend, &[Key::Char('>')]);
+        keymap.bind(Action::ScrollUp, &[Key::PageUp, Key::Char('[')]);
+        keymap.bind(Action::ScrollDown, &[Key::PageDown, Key::Char(']')]);
+        keymap.bind(Action::MessageHistory, &[Key::Char('m')]);
+        keymap.bind(Action::Confirm, &[Key::Char(' '), Key::Char('\n')]);
+        keymap.bind(Action::Cancel, &[Key::Esc]);
+        keymap
+    }
+
+    pub fn preset(name: &str) -> Option<Keymap> {
+        let mut keymap = Keymap::common();
+        match name {
+            "default" => {
+                keymap.bind(Action::MoveUp, &[Key::Char('w')]);
+                keymap.bind(Action::MoveDown, &[Key::Char('s')]);
+                keymap.bind(Action::MoveLeft, &[Key::Char('a')]);
+                keymap.bind(Action::MoveRight, &[Key::Char('d')]);
+                keymap.bind(Action::MoveUpLeft, &[Key::Char('q')]);
+                keymap.bind(Action::MoveUpRight, &[Key::Char('e')]);
+                keymap.bind(Action::MoveDownLeft, &[Key::Char('z')]);
+                keymap.bind(Action::MoveDownRight, &[Key::Char('c')]);
+            }
+            "arrows" => {
+                keymap.bind(Action::MoveUp, &[Key::Up]);
+                keymap.bind(Action::MoveDown, &[Key::Down]);
+                keymap.bind(Action::MoveLeft, &[Key::Left]);
+                keymap.bind(Action::MoveRight, &[Key::Right]);
+                // Not what the numpad sends with Num Lock off, since
+                // PgUp and PgDn scroll the messages
+                keymap.bind(Action::MoveUpLeft, &[Key::Char('q')]);
+                keymap.bind(Action::MoveUpRight, &[Key::Char('e')]);
+                keymap.bind(Action::MoveDownLeft, &[Key::Char('z')]);
+                keymap.bind(Action::MoveDownRight, &[Key::Char('c')]);
+            }
+            "vi" => {
+                keymap.bind(Action::MoveUp, &[Key::Char('k')]);
+                keymap.bind(Action::MoveDown, &[Key::Char('j')]);
+                keymap.bind(Action::MoveLeft, &[Key::Char('h')]);
+                keymap.bind(Action::MoveRight, &[Key::Char('l')]);
+                keymap.bind(Action::MoveUpLeft, &[Key::Char('y')]);
+                keymap.bind(Action::MoveUpRight, &[Key::Char('u')]);
+                keymap.bind(Action::MoveDownLeft, &[Key::Char('b')]);
+                keymap.bind(Action::MoveDownRight, &[Key::Char('n')]);
+            }
+            "numpad" => {
+                // The digits are taken by movement
+                keymap.bind(Action::MoveUp, &[Key::Char('8')]);
+                keymap.bind(Action::MoveDown, &[Key::Char('2')]);
+                keymap.bind(Action::MoveLeft, &[Key::Char('4')]);
+                keymap.bind(Action::MoveRight, &[Key::Char('6')]);
+                keymap.bind(Action::MoveUpLeft, &[Key::Char('7')]);
+                keymap.bind(Action::MoveUpRight, &[Key::Char('9')]);
+                keymap.bind(Action::MoveDownLeft, &[Key::Char('1')]);
+                keymap.bind(Action::MoveDownRight, &[Key::Char('3')]);
+                keymap.bind(Action::Wait, &[Key::Char('5'), Key::Char('.')]);
+                keymap.bind(Action::Examine, &[Key::Char('x')]);
+                keymap.bind(Action::Crouch, &[Key::Char('c')]);
+                keymap.bind(Action::FirePrimary, &[Key::Char('f')]);
+                keymap.bind(Action::FireSecondary, &[Key::Char('g')]);
+            }
+            _ => return None,
+        }
+        Some(keymap)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Keymap, KeymapError> {
+        let mut source = String::new();
+        File::open(path)?.read_to_string(&mut source)?;
+        Keymap::parse(&source)
+    }
+
+    /// Parse a keymap file: a preset to start from, plus a table of
+    /// actions whose keys should be replaced.
+    pub fn parse(source: &str) -> Result<Keymap, KeymapError> {
+        let raw: RawKeymap = toml::from_str(source)?;
+
+        let preset = raw.preset.unwrap_or_else(|| "default".into());
+        let mut keymap = match Keymap::preset(&preset) {
+            Some(keymap) => keymap,
+            None => return Err(KeymapError::Invalid(
+                "preset".into(),
+                format!("unknown preset \"{}\" (try one of {})", preset, PRESETS.join(", ")))),
+        };
+
+        if let Some(bindings) = raw.bindings {
+            for (name, keys) in bindings.iter() {
+                let location = format!("bindings.{}", name);
+                let action = match Action::from_name(name) {
+                    Some(action) => action,
+                    None => return Err(KeymapError::Invalid(location, "unknown action".into())),
+                };
+
+                let mut parsed = Vec::new();
+                for key in keys.iter() {
+                    match parse_key(key) {
+                        Some(key) => parsed.push(key),
+                        None => return Err(KeymapError::Invalid(location.clone(), format!("unknown key \"{}\"", key))),
+                    }
+                }
+                keymap.bind(action, &parsed);
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    /// Replace the keys bound to an action. The keys are taken away
+    /// from whatever action they were bound to before.
+    pub fn bind(&mut self, action: Action, keys: &[Key]) {
+        self.bindings.retain(|&(k, a)| a != action && !keys.contains(&k));
+        for key in keys {
+            self.bindings.push((*key, action));
+        }
+    }
+
+    /// Exactly these bindings, e.g. as recorded from another keymap.
+    pub fn from_bindings(bindings: Vec<(Key, Action)>) -> Keymap {
+        Keymap {
+            bindings: bindings,
+        }
+    }
+
+    /// Every key and the action it triggers, in the order they were
+    /// bound.
+    pub fn bindings(&self) -> &[(Key, Action)] {
+        &self.bindings
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings.iter().find(|&&(k, _)| k == key).map(|&(_, action)| action)
+    }
+
+    /// The keys bound to an action, in the order they were bound.
+    pub fn keys(&self, action: Action) -> Vec<Key> {
+        self.bindings.iter().filter(|&&(_, a)| a == action).map(|&(key, _)| key).collect()
+    }
+
+    /// A short label for the first key bound to an action, for help
+    /// text.
+    pub fn label(&self, action: Action) -> String {
+        self.keys(action).first().map_or("-".into(), |&key| key_name(key))
+    }
+
+    /// A label for the four orthogonal movement keys together, e.g.
+    /// `wasd`.
+    pub fn movement_label(&self) -> String {
+        [Action::MoveUp, Action::MoveLeft, Action::MoveDown, Action::MoveRight].iter()
+            .map(|&action| self.label(action))
+            .collect()
+    }
+
+    /// A label for the four diagonal movement keys together, e.g.
+    /// `qezc`. Falls back to arrows if the keys have long names (like
+    /// Home and PgUp), since the help panel is narrow.
+    pub fn diagonal_label(&self) -> String {
+        let labels: Vec<String> = [Action::MoveUpLeft, Action::MoveUpRight, Action::MoveDownLeft, Action::MoveDownRight].iter()
+            .map(|&action| self.label(action))
+            .collect();
+        if labels.iter().all(|label| label.chars().count() == 1) {
+            labels.concat()
+        }
+        else {
+            "↖↗↙↘".into()
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::preset("default").unwrap()
+    }
+}
+
+/// Parse a key name: either a single character or one of `esc`,
+/// `space`, `tab`, `enter`, `backspace`, `up`, `down`, `left`,
+/// `right`, `home`, `end`, `pageup`, `pagedown`.
+pub fn parse_key(token: &str) -> Option<Key> {
+    let mut chars = token.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Char(c));
+    }
+
+    match token {
+        "esc" => Some(Key::Esc),
+        "space" => Some(Key::Char(' ')),
+        "tab" => Some(Key::Char('\t')),
+        "enter" => Some(Key::Char('\n')),
+        "backspace" => Some(Key::Backspace),
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "pageup" => Some(Key::PageUp),
+        "pagedown" => Some(Key::PageDown),
+        _ => None,
+    }
+}
+
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "Space".into(),
+        Key::Char('\t') => "Tab".into(),
+        Key::Char('\n') => "Enter".into(),
+        Key::Char(c) => c.to_string(),
+        Key::Esc => "Esc".into(),
+        Key::Backspace => "Bksp".into(),
+        Key::Up => "↑".into(),
+        Key::Down => "↓".into(),
+        Key::Left => "←".into(),
+        Key::Right => "→".into(),
+        Key::Home => "Home".into(),
+        Key::End => "End".into(),
+        Key::PageUp => "PgUp".into(),
+        Key::PageDown => "PgDn".into(),
+        _ => "?".into(),
+    }
+}
@@ -17,12 +17,23 @@ extern crate env_logger;
 extern crate log;
 extern crate log_panics;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 extern crate specs;
 extern crate termion;
 extern crate time;
+extern crate toml;
 extern crate voodoo;
 
 pub mod components;
+pub mod content;
+pub mod events;
+pub mod headless;
+pub mod keymap;
+pub mod replay;
+pub mod save;
 pub mod screen;
 pub mod systems;
 pub mod ui;
@@ -33,6 +44,8 @@ use std::thread;
 
 const MS: u64 = 1_000_000;
 const TICK_TIME: u64 = MS * 25;
+/// How many ticks to run per frame when replaying with `--fast`.
+const FAST_TICKS: u64 = 20;
 
 const WIDTH: u16 = 80;
 const HEIGHT: u16 = 24;
@@ -40,6 +53,68 @@ const HEIGHT: u16 = 24;
 const MAP_WIDTH: u16 = 38;
 const MAP_HEIGHT: u16 = 18;
 
+/// Options given on the command line.
+struct Options {
+    seed: Option<u64>,
+    content: String,
+    keys: String,
+    headless: Option<String>,
+    record: Option<String>,
+    replay: Option<String>,
+    fast: bool,
+}
+
+fn usage() -> ! {
+    println!("Usage: labyrinth [--seed <number>] [--content <file>] [--keys <file>] [--headless <script>]");
+    println!("                 [--record <file>] [--replay <file> [--fast]]");
+    std::process::exit(1);
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        seed: None,
+        content: content::CONTENT_PATH.into(),
+        keys: keymap::KEYMAP_PATH.into(),
+        headless: None,
+        record: None,
+        replay: None,
+        fast: false,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--seed" => {
+                options.seed = match args.next().and_then(|s| s.parse().ok()) {
+                    Some(seed) => Some(seed),
+                    None => usage(),
+                };
+            }
+            "--content" => {
+                options.content = args.next().unwrap_or_else(|| usage());
+            }
+            "--keys" => {
+                options.keys = args.next().unwrap_or_else(|| usage());
+            }
+            "--headless" => {
+                options.headless = Some(args.next().unwrap_or_else(|| usage()));
+            }
+            "--record" => {
+                options.record = Some(args.next().unwrap_or_else(|| usage()));
+            }
+            "--replay" => {
+                options.replay = Some(args.next().unwrap_or_else(|| usage()));
+            }
+            "--fast" => {
+                options.fast = true;
+            }
+            _ => usage(),
+        }
+    }
+
+    options
+}
+
 fn async_events<R: std::io::Read + Send + 'static>(stdin: R) -> mpsc::Receiver<std::io::Result<termion::event::Event>> {
     use termion::input::TermRead;
 
@@ -54,7 +129,59 @@ fn async_events<R: std::io::Read + Send + 'static>(stdin: R) -> mpsc::Receiver<s
     rx
 }
 
-fn run() -> f64 {
+fn make_planner(seed: u64, content: content::Content, keymap: keymap::Keymap) -> specs::Planner<()> {
+    let mut world = specs::World::new();
+    components::register_all(&mut world);
+    world.add_resource(util::rng::Rngs::new(seed));
+    world.add_resource(content);
+    world.add_resource(keymap);
+    specs::Planner::<()>::new(world, 2)
+}
+
+/// Play a script of key presses without a terminal, then print
+/// where the player ended up.
+fn run_headless(seed: u64, content: content::Content, script: &str) {
+    use specs::Join;
+
+    let events = match headless::parse_script(script) {
+        Ok(events) => events,
+        Err(e) => {
+            println!("Error in script: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Scripts are written against the default bindings
+    let mut game = headless::Headless::new(make_planner(seed, content, keymap::Keymap::default()));
+    if !game.generate_level() {
+        println!("Level generation didn't finish");
+        std::process::exit(1);
+    }
+    game.run(&events);
+
+    let ticks = game.ticks;
+    let finished = game.finished;
+    let world = game.world();
+    for message in world.read_resource::<systems::ui::MessageLog>().messages.iter() {
+        println!("{}", message);
+    }
+    println!("---");
+    println!("Seed: {}", world.read_resource::<util::rng::Rngs>().seed);
+    println!("Ticks: {}", ticks);
+    println!("Turns: {}", world.read_resource::<components::turn::Scheduler>().turns);
+    let players = world.read::<components::player::Player>();
+    let positions = world.read::<components::position::Position>();
+    let healths = world.read::<components::health::Health>();
+    for (_, position, health) in (&players, &positions, &healths).iter() {
+        println!("Player: ({}, {}), health {}/{}", position.x, position.y, health.health, health.max_health);
+    }
+    if let Some(state) = finished {
+        println!("Finished: {:?}", state);
+    }
+}
+
+fn run(options: Options, seed: u64, content: content::Content, keymap: keymap::Keymap,
+       mut replay: Option<replay::Replay>) -> f64 {
     use std::time::Duration;
 
     use voodoo::color::ColorValue;
@@ -63,9 +190,10 @@ fn run() -> f64 {
     env_logger::init().unwrap();
     log_panics::init();
 
-    let mut world = specs::World::new();
-    components::register_all(&mut world);
-    let mut planner = specs::Planner::<()>::new(world, 2);
+    let mut recorder = options.record.as_ref().map(|path| {
+        replay::Recorder::create(path, seed, &keymap).expect("Couldn't create replay file")
+    });
+    let mut planner = make_planner(seed, content, keymap);
 
     // Initialize the console
     let (terminal, stdin, mut stdout) = Terminal::new();
@@ -80,12 +208,35 @@ fn run() -> f64 {
     let mut dt = 0;
     let mut avg_frame_time = 0.0;
     let mut frames: u64 = 0;
+    let mut tick: u64 = 0;
 
-    let mut state = screen::StateManager::new(&mut planner, screen::StateTransition::Game);
+    // A recorded or replayed run has to start from the seed, not a save
+    let initial_state = if recorder.is_none() && replay.is_none() && save::exists(save::SAVE_PATH) {
+        screen::StateTransition::Continue
+    }
+    else {
+        screen::StateTransition::Game
+    };
+    let mut state = screen::StateManager::new(&mut planner, initial_state);
 
     'main: loop {
+        let replaying = replay.as_ref().map_or(false, |r| !r.is_finished());
+
+        // Make sure the previous tick is done, so the event is seen
+        // by the tick it's recorded against
+        planner.wait();
         for event in rx.try_iter() {
             if let Ok(event) = event {
+                if replaying {
+                    // Only allow quitting while a replay is running
+                    if event == termion::event::Event::Key(termion::event::Key::Esc) {
+                        break 'main;
+                    }
+                    continue;
+                }
+                if let Some(ref mut recorder) = recorder {
+                    recorder.record(tick, &event).expect("Couldn't write replay file");
+                }
                 state.dispatch(event);
             }
         }
@@ -94,18 +245,36 @@ fn run() -> f64 {
         let old_tick = last_tick;
         dt += now - last_tick;
         last_tick = now;
+        if replaying && options.fast {
+            dt += TICK_TIME * FAST_TICKS;
+        }
 
         while dt > TICK_TIME {
             dt -= TICK_TIME;
+            if let Some(ref mut replay) = replay {
+                for event in replay.due(tick) {
+                    state.dispatch(event);
+                }
+            }
             planner.dispatch(());
+            planner.wait();
+            tick += 1;
+
+            // Switch screens between ticks, so that a replay sees
+            // the same screen at the same tick however fast it runs
+            if state.update(&mut planner) {
+                break 'main;
+            }
         }
 
         state.render(&mut planner, &mut compositor);
         compositor.display(&mut stdout);
-        if state.update(&mut planner) {
-            break 'main;
+        if replaying && options.fast {
+            dt = 0;
+        }
+        else {
+            thread::sleep(Duration::from_millis((TICK_TIME - dt) / MS));
         }
-        thread::sleep(Duration::from_millis((TICK_TIME - dt) / MS));
 
         let frame_time = time::precise_time_ns() - old_tick;
         avg_frame_time = ((frames as f64 * avg_frame_time) + frame_time as f64) / (frames as f64 + 1.0);
@@ -116,6 +285,55 @@ fn run() -> f64 {
 }
 
 fn main() {
-    let avg_frame_time = run();
+    let options = parse_args();
+    // Load content before taking over the terminal, so errors are readable
+    let content = match content::Content::load(&options.content) {
+        Ok(content) => content,
+        Err(e) => {
+            println!("Error loading {}: {}", options.content, e);
+            std::process::exit(1);
+        }
+    };
+
+    // The key bindings file is optional
+    let keymap = if std::path::Path::new(&options.keys).exists() {
+        match keymap::Keymap::load(&options.keys) {
+            Ok(keymap) => keymap,
+            Err(e) => {
+                println!("Error loading {}: {}", options.keys, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    else {
+        keymap::Keymap::default()
+    };
+
+    let replay = options.replay.as_ref().map(|path| match replay::Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            println!("Error loading {}: {}", path, e);
+            std::process::exit(1);
+        }
+    });
+    // A replay has to use the seed and keys it was recorded with
+    let seed = replay.as_ref().map(|r| r.seed)
+        .or(options.seed)
+        .unwrap_or_else(time::precise_time_ns);
+    let keymap = replay.as_ref().and_then(|r| r.keymap.clone()).unwrap_or(keymap);
+
+    if let Some(ref path) = options.headless {
+        use std::io::Read;
+
+        let mut script = String::new();
+        if let Err(e) = std::fs::File::open(path).and_then(|mut f| f.read_to_string(&mut script)) {
+            println!("Error loading {}: {}", path, e);
+            std::process::exit(1);
+        }
+        run_headless(seed, content, &script);
+        return;
+    }
+
+    let avg_frame_time = run(options, seed, content, keymap, replay);
     println!("Average frame time: {:03.03} ms", avg_frame_time / MS as f64);
 }
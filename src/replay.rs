@@ -0,0 +1,250 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Recording and replaying input.
+//!
+//! A replay file starts with a `seed <n>` line, then a `bind <action>
+//! <key>` line for every key binding, followed by one line per input
+//! event: the tick it was dispatched before, then the event. Since
+//! every random decision comes from the seeded RNG, and the keys are
+//! replayed with the bindings they were recorded with, this is enough
+//! to reproduce a run exactly.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use termion::event::{Event, Key, MouseButton, MouseEvent};
+
+use ::keymap::{Action, Keymap};
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// A line couldn't be understood.
+    Parse(usize, String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref e) => write!(f, "{}", e),
+            ReplayError::Parse(line, ref message) => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> ReplayError {
+        ReplayError::Io(e)
+    }
+}
+
+pub struct Recorder {
+    out: File,
+}
+
+pub struct Replay {
+    pub seed: u64,
+    /// The key bindings the replay was recorded with, if it says.
+    pub keymap: Option<Keymap>,
+    events: VecDeque<(u64, Event)>,
+}
+
+fn encode(event: &Event) -> Option<String> {
+    Some(match *event {
+        Event::Key(key) => match key {
+            Key::Char(c) => format!("char {}", c as u32),
+            Key::Alt(c) => format!("alt {}", c as u32),
+            Key::Ctrl(c) => format!("ctrl {}", c as u32),
+            Key::F(n) => format!("f {}", n),
+            Key::Esc => "esc".into(),
+            Key::Backspace => "backspace".into(),
+            Key::Left => "left".into(),
+            Key::Right => "right".into(),
+            Key::Up => "up".into(),
+            Key::Down => "down".into(),
+            Key::Home => "home".into(),
+            Key::End => "end".into(),
+            Key::PageUp => "pageup".into(),
+            Key::PageDown => "pagedown".into(),
+            Key::Delete => "delete".into(),
+            Key::Insert => "insert".into(),
+            Key::Null => "null".into(),
+            _ => return None,
+        },
+        Event::Mouse(MouseEvent::Press(button, x, y)) => format!("press {} {} {}", button_name(button), x, y),
+        Event::Mouse(MouseEvent::Release(x, y)) => format!("release {} {}", x, y),
+        Event::Mouse(MouseEvent::Hold(x, y)) => format!("hold {} {}", x, y),
+        _ => return None,
+    })
+}
+
+fn button_name(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "left",
+        MouseButton::Right => "right",
+        MouseButton::Middle => "middle",
+        MouseButton::WheelUp => "wheelup",
+        MouseButton::WheelDown => "wheeldown",
+    }
+}
+
+fn parse_button(name: &str) -> Option<MouseButton> {
+    match name {
+        "left" => Some(MouseButton::Left),
+        "right" => Some(MouseButton::Right),
+        "middle" => Some(MouseButton::Middle),
+        "wheelup" => Some(MouseButton::WheelUp),
+        "wheeldown" => Some(MouseButton::WheelDown),
+        _ => None,
+    }
+}
+
+fn decode(tokens: &[&str]) -> Option<Event> {
+    let number = |index: usize| tokens.get(index).and_then(|t| t.parse::<u16>().ok());
+    let character = |index: usize| tokens.get(index)
+        .and_then(|t| t.parse::<u32>().ok())
+        .and_then(::std::char::from_u32);
+
+    let key = match tokens.get(0).cloned() {
+        Some("char") => character(1).map(Key::Char),
+        Some("alt") => character(1).map(Key::Alt),
+        Some("ctrl") => character(1).map(Key::Ctrl),
+        Some("f") => number(1).map(|n| Key::F(n as u8)),
+        Some("esc") => Some(Key::Esc),
+        Some("backspace") => Some(Key::Backspace),
+        Some("left") => Some(Key::Left),
+        Some("right") => Some(Key::Right),
+        Some("up") => Some(Key::Up),
+        Some("down") => Some(Key::Down),
+        Some("home") => Some(Key::Home),
+        Some("end") => Some(Key::End),
+        Some("pageup") => Some(Key::PageUp),
+        Some("pagedown") => Some(Key::PageDown),
+        Some("delete") => Some(Key::Delete),
+        Some("insert") => Some(Key::Insert),
+        Some("null") => Some(Key::Null),
+        Some("press") => {
+            let button = tokens.get(1).and_then(|t| parse_button(t));
+            return match (button, number(2), number(3)) {
+                (Some(button), Some(x), Some(y)) => Some(Event::Mouse(MouseEvent::Press(button, x, y))),
+                _ => None,
+            };
+        }
+        Some(kind) => {
+            return match (kind, number(1), number(2)) {
+                ("release", Some(x), Some(y)) => Some(Event::Mouse(MouseEvent::Release(x, y))),
+                ("hold", Some(x), Some(y)) => Some(Event::Mouse(MouseEvent::Hold(x, y))),
+                _ => None,
+            };
+        }
+        None => None,
+    };
+
+    key.map(Event::Key)
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P, seed: u64, keymap: &Keymap) -> io::Result<Recorder> {
+        let mut out = File::create(path)?;
+        writeln!(out, "seed {}", seed)?;
+        for &(key, action) in keymap.bindings() {
+            if let Some(encoded) = encode(&Event::Key(key)) {
+                writeln!(out, "bind {} {}", action.name(), encoded)?;
+            }
+        }
+        Ok(Recorder {
+            out: out,
+        })
+    }
+
+    /// Record an event dispatched before the given tick. Each line is
+    /// written immediately so that the replay survives a crash.
+    pub fn record(&mut self, tick: u64, event: &Event) -> io::Result<()> {
+        if let Some(encoded) = encode(event) {
+            writeln!(self.out, "{} {}", tick, encoded)?;
+        }
+        Ok(())
+    }
+}
+
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        let file = BufReader::new(File::open(path)?);
+        let mut seed = None;
+        let mut bindings = Vec::new();
+        let mut events = VecDeque::new();
+
+        for (index, line) in file.lines().enumerate() {
+            let line = line?;
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() {
+                continue;
+            }
+
+            if seed.is_none() {
+                seed = match (tokens[0], tokens.get(1).and_then(|s| s.parse().ok())) {
+                    ("seed", Some(seed)) => Some(seed),
+                    _ => return Err(ReplayError::Parse(index + 1, "expected \"seed <number>\"".into())),
+                };
+                continue;
+            }
+
+            if tokens[0] == "bind" && events.is_empty() {
+                let action = tokens.get(1).and_then(|name| Action::from_name(name));
+                match (action, decode(tokens.get(2..).unwrap_or(&[]))) {
+                    (Some(action), Some(Event::Key(key))) => bindings.push((key, action)),
+                    _ => return Err(ReplayError::Parse(index + 1, format!("bad binding \"{}\"", line))),
+                }
+                continue;
+            }
+
+            let tick = match tokens[0].parse() {
+                Ok(tick) => tick,
+                Err(_) => return Err(ReplayError::Parse(index + 1, format!("bad tick \"{}\"", tokens[0]))),
+            };
+            match decode(&tokens[1..]) {
+                Some(event) => events.push_back((tick, event)),
+                None => return Err(ReplayError::Parse(index + 1, format!("bad event \"{}\"", line))),
+            }
+        }
+
+        match seed {
+            Some(seed) => Ok(Replay {
+                seed: seed,
+                // Older replays don't record their bindings
+                keymap: if bindings.is_empty() { None } else { Some(Keymap::from_bindings(bindings)) },
+                events: events,
+            }),
+            None => Err(ReplayError::Parse(1, "empty replay".into())),
+        }
+    }
+
+    /// Take the events that were dispatched before the given tick.
+    pub fn due(&mut self, tick: u64) -> Vec<Event> {
+        let mut result = Vec::new();
+        while self.events.front().map_or(false, |&(t, _)| t <= tick) {
+            result.push(self.events.pop_front().unwrap().1);
+        }
+        result
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
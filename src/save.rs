@@ -0,0 +1,276 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use serde_json;
+use specs::{self, Join};
+
+use ::components::{ai, combat, drawable, health, input, map, noise, player, position, status, turn, ui};
+use ::systems::ui::{Message, MessageLog};
+use ::util::rng::Rngs;
+
+pub const SAVE_PATH: &'static str = "labyrinth.sav";
+
+/// The components of a single entity in the game world.
+#[derive(Clone,Debug,Default,Serialize,Deserialize)]
+pub struct SavedEntity {
+    pub position: Option<position::Position>,
+    pub movable: bool,
+    pub health: Option<health::Health>,
+    pub equip: Option<player::Equip>,
+    pub inventory: Option<player::Inventory>,
+    pub chase: Option<ai::ChaseBehavior>,
+    pub behavior: Option<ai::Behavior>,
+    pub perception: Option<ai::Perception>,
+    pub cover: Option<health::Cover>,
+    pub drops_loot: Option<player::DropsLoot>,
+    pub grabbable: Option<player::Grabbable>,
+    pub drawable: Option<drawable::Glyph>,
+    pub damage_reduction: Option<combat::DamageReduction>,
+    pub temporary_damage_reduction: Option<combat::TemporaryDamageReduction>,
+    pub player: Option<player::Player>,
+    pub actor: Option<turn::Actor>,
+    pub status: Option<status::StatusEffects>,
+    pub crouching: bool,
+    pub sneaking: bool,
+    pub focus: bool,
+}
+
+/// A snapshot of a running game. Map contents aren't stored
+/// directly; every saved entity with a position occupies its cell
+/// when restored.
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct SaveGame {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<map::MapCell>,
+    pub damage: Vec<usize>,
+    pub visibility: Vec<map::Visibility>,
+    pub depth: usize,
+    pub entities: Vec<SavedEntity>,
+    pub messages: Vec<Message>,
+    pub rngs: Rngs,
+    pub scheduler: turn::Scheduler,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Format(serde_json::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveError::Io(ref e) => write!(f, "{}", e),
+            SaveError::Format(ref e) => write!(f, "corrupt save file: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> SaveError {
+        SaveError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> SaveError {
+        SaveError::Format(e)
+    }
+}
+
+pub fn exists<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().exists()
+}
+
+impl SaveGame {
+    /// Capture every positioned entity in the world.
+    pub fn capture(world: &specs::World) -> SaveGame {
+        let map = world.read_resource::<map::Map>();
+        let dungeon = world.read_resource::<map::Dungeon>();
+        let log = world.read_resource::<MessageLog>();
+        let rngs = world.read_resource::<Rngs>();
+        let scheduler = world.read_resource::<turn::Scheduler>();
+
+        let entities = world.entities();
+        let positions = world.read::<position::Position>();
+        let movables = world.read::<input::Movable>();
+        let healths = world.read::<health::Health>();
+        let equips = world.read::<player::Equip>();
+        let inventories = world.read::<player::Inventory>();
+        let chasers = world.read::<ai::ChaseBehavior>();
+        let behaviors = world.read::<ai::Behavior>();
+        let perceptions = world.read::<ai::Perception>();
+        let covers = world.read::<health::Cover>();
+        let drops_loot = world.read::<player::DropsLoot>();
+        let grabbables = world.read::<player::Grabbable>();
+        let drawables = world.read::<drawable::StaticDrawable>();
+        let drs = world.read::<combat::DamageReduction>();
+        let temp_drs = world.read::<combat::TemporaryDamageReduction>();
+        let players = world.read::<player::Player>();
+        let actors = world.read::<turn::Actor>();
+        let statuses = world.read::<status::StatusEffects>();
+        let crouching = world.read::<combat::Crouching>();
+        let sneaking = world.read::<noise::Sneaking>();
+        let focused = world.read::<ui::Focus>();
+        let dead = world.read::<ai::Dead>();
+
+        let mut saved = Vec::new();
+        for (entity, position, _) in (&entities, &positions, !&dead).iter() {
+            saved.push(SavedEntity {
+                position: Some(*position),
+                movable: movables.get(entity).is_some(),
+                health: healths.get(entity).cloned(),
+                equip: equips.get(entity).cloned(),
+                inventory: inventories.get(entity).cloned(),
+                chase: chasers.get(entity).cloned(),
+                behavior: behaviors.get(entity).cloned(),
+                perception: perceptions.get(entity).cloned(),
+                cover: covers.get(entity).cloned(),
+                drops_loot: drops_loot.get(entity).cloned(),
+                grabbable: grabbables.get(entity).cloned(),
+                drawable: drawables.get(entity).map(|d| d.glyph),
+                damage_reduction: drs.get(entity).cloned(),
+                temporary_damage_reduction: temp_drs.get(entity).cloned(),
+                player: players.get(entity).cloned(),
+                actor: actors.get(entity).cloned(),
+                status: statuses.get(entity).cloned(),
+                crouching: crouching.get(entity).is_some(),
+                sneaking: sneaking.get(entity).is_some(),
+                focus: focused.get(entity).is_some(),
+            });
+        }
+
+        SaveGame {
+            width: map.width,
+            height: map.height,
+            cells: map.map.clone(),
+            damage: map.damage.clone(),
+            visibility: map.visibility.clone(),
+            depth: dungeon.depth,
+            entities: saved,
+            messages: log.messages.clone(),
+            rngs: rngs.clone(),
+            scheduler: scheduler.clone(),
+        }
+    }
+
+    /// Replace the map, message log and RNG state of the world with
+    /// the saved ones, and recreate the saved entities.
+    pub fn restore(self, world: &mut specs::World) {
+        let mut map = map::Map::new(self.width, self.height);
+        map.map = self.cells;
+        map.damage = self.damage;
+        map.visibility = self.visibility;
+
+        for saved in self.entities {
+            let mut builder = world.create_now();
+            if saved.movable {
+                builder = builder.with(input::Movable);
+            }
+            if let Some(c) = saved.health {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.equip {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.inventory {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.chase {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.behavior {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.perception {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.cover {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.drops_loot {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.grabbable {
+                builder = builder.with(c);
+            }
+            if let Some(glyph) = saved.drawable {
+                builder = builder.with(drawable::StaticDrawable::new(glyph));
+            }
+            if let Some(c) = saved.damage_reduction {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.temporary_damage_reduction {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.player {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.actor {
+                builder = builder.with(c);
+            }
+            if let Some(c) = saved.status {
+                builder = builder.with(c);
+            }
+            if saved.crouching {
+                builder = builder.with(combat::Crouching);
+            }
+            if saved.sneaking {
+                builder = builder.with(noise::Sneaking);
+            }
+            if saved.focus {
+                builder = builder.with(ui::Focus);
+            }
+            if let Some(c) = saved.position {
+                builder = builder.with(c);
+            }
+            let entity = builder.build();
+
+            if let Some(position) = saved.position {
+                map.fill(entity, position.x, position.y);
+            }
+        }
+
+        *world.write_resource::<map::Map>() = map;
+        world.write_resource::<map::Dungeon>().depth = self.depth;
+        world.write_resource::<MessageLog>().messages = self.messages;
+        *world.write_resource::<Rngs>() = self.rngs;
+        *world.write_resource::<turn::Scheduler>() = self.scheduler;
+    }
+}
+
+pub fn save<P: AsRef<Path>>(world: &specs::World, path: P) -> Result<(), SaveError> {
+    let game = SaveGame::capture(world);
+    let file = File::create(path)?;
+    serde_json::to_writer(&mut io::BufWriter::new(file), &game)?;
+    Ok(())
+}
+
+/// Load a saved game. The save file is removed once read, so a run
+/// can't be continued more than once.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<SaveGame, SaveError> {
+    let game = {
+        let file = File::open(path.as_ref())?;
+        serde_json::from_reader(io::BufReader::new(file))?
+    };
+    fs::remove_file(path)?;
+    Ok(game)
+}
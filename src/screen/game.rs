@@ -29,23 +29,53 @@ pub enum SubGameScreen {
     Map,
     Inventory,
     Equip,
+    /// Every message so far, over the whole screen.
+    History,
 }
 
 pub struct GameScreen {
     sub_screen: Vec<SubGameScreen>,
     map_frame: Window,
     msg_frame: Window,
+    history_frame: Window,
     event_channel: mpsc::Sender<components::input::Event>,
     sub_screen_channel: mpsc::Receiver<super::SubScreenEvent<SubGameScreen>>,
 }
 
-impl super::Screen for GameScreen {
-    fn setup(planner: &mut specs::Planner<()>, transitions: super::TransitionChannel) -> GameScreen {
+impl GameScreen {
+    /// Set up the game from the save file, falling back to a new game
+    /// if it can't be loaded.
+    pub fn resume(planner: &mut specs::Planner<()>, transitions: super::TransitionChannel) -> GameScreen {
+        match ::save::load(::save::SAVE_PATH) {
+            Ok(game) => GameScreen::new(planner, transitions, Some(game)),
+            Err(e) => {
+                let screen = GameScreen::new(planner, transitions, None);
+                planner.mut_world().read_resource::<::events::EventBus>().notice(
+                    format!("Couldn't load saved game: {}", e));
+                screen
+            }
+        }
+    }
+
+    /// Send an input event straight to the game, bypassing the
+    /// terminal.
+    pub fn send(&mut self, event: components::input::Event) {
+        self.event_channel.send(event).unwrap();
+    }
+
+    fn new(planner: &mut specs::Planner<()>, transitions: super::TransitionChannel,
+           saved: Option<::save::SaveGame>) -> GameScreen {
         let (sub_screen_sender, sub_screen_channel) = mpsc::channel();
+        let keymap = planner.mut_world().read_resource::<::keymap::Keymap>().clone();
+        let scroll_label = format!("{}/{}—Scroll", keymap.label(::keymap::Action::ScrollUp),
+                                   keymap.label(::keymap::Action::ScrollDown));
 
-        let (map_frame, msg_frame) = {
+        let (map_frame, msg_frame, history_frame) = {
             let world = planner.mut_world();
             world.add_resource(components::map::Map::new(100, 100));
+            world.add_resource(components::map::Dungeon::new());
+            world.add_resource(systems::ui::MessageLog::new());
+            world.add_resource(components::turn::Scheduler::new());
             world.add_resource(systems::ui::InfoPanelResource::new(
                 Window::new(Point::new(MAP_WIDTH + 2, 0), 80 - 2 - MAP_WIDTH, 2)));
             world.add_resource(systems::ui::CommandPanelResource::new(
@@ -56,57 +86,85 @@ impl super::Screen for GameScreen {
             map_frame.print_at(Point::new(1, 0), "MAP");
             let mut msg_frame = Window::new(Point::new(MAP_WIDTH + 2, 2), WIDTH - 2 - MAP_WIDTH, HEIGHT - 2);
             let y = msg_frame.height - 1;
-            msg_frame.print_at(Point::new(1, y), "PgUp/Down—Scroll");
+            msg_frame.print_at(Point::new(1, y), &format!(
+                "{} {}—History", scroll_label, keymap.label(::keymap::Action::MessageHistory)));
             let point = Point::new(msg_frame.position.x + 1, msg_frame.position.y + 1);
             world.add_resource(systems::ui::MessagesPanelResource::new(
                 Window::new(point, msg_frame.width - 2, msg_frame.height - 2)));
             world.add_resource(systems::ui::InventoryPanelResource::new(
                 Window::new(point, msg_frame.width - 2, msg_frame.height - 2)));
 
-            (map_frame, msg_frame)
+            let mut history_frame = Window::new(Point::new(0, 0), WIDTH, HEIGHT);
+            let y = history_frame.height - 1;
+            history_frame.print_at(Point::new(1, y), &format!(
+                "{} {}—Filter {}—Close", scroll_label, keymap.label(::keymap::Action::SwitchList),
+                keymap.label(::keymap::Action::Cancel)));
+            world.add_resource(systems::ui::HistoryPanelResource::new(
+                Window::new(Point::new(1, 1), WIDTH - 2, HEIGHT - 2)));
+
+            (map_frame, msg_frame, history_frame)
         };
 
         // Setup systems
-        let msg_resource = {
-            let (sys, res) = systems::ui::MessagesPanelSystem::new();
-            planner.add_system(sys, "messages", 1);
-            res
-        };
-        let (ab_tx, ab_rx) = mpsc::channel();
-        let (ae_tx, ae_rx) = mpsc::channel();
+        let events = ::events::EventBus::new();
+        planner.mut_world().add_resource(events.clone());
+        planner.add_system(systems::ui::MessagesPanelSystem::new(&events), "messages", 1);
+        let (action_tx, action_rx) = mpsc::channel();
+        let (turn_end_tx, turn_end_rx) = mpsc::channel();
+        let (descend_tx, descend_rx) = mpsc::channel();
 
         let (input_system, event_channel) = components::input::InputSystem::new(
             ::ui::List::new(Point::new(0, 0), msg_frame.width - 2, msg_frame.height - 2),
-            transitions.clone(), sub_screen_sender, msg_resource.clone(), ab_tx, ae_rx);
+            keymap,
+            transitions.clone(), sub_screen_sender, events.clone(),
+            action_tx, turn_end_rx, descend_tx);
         planner.add_system(input_system, "input", 100);
         planner.add_system(components::drawable::RenderSystem::new(), "drawable_render", 10);
         planner.add_system(components::map::RenderSystem::new(), "map_render", 10);
-        planner.add_system(components::map::BuilderSystem::new(msg_resource.clone()), "map_build", 20);
-        planner.add_system(systems::ai::AiSystem::new(msg_resource.clone(), ab_rx, ae_tx), "ai", 1);
-        planner.add_system(systems::ai::DeadSystem::new(transitions.clone()), "dead", 1);
-        planner.add_system(systems::combat::CombatSystem::new(msg_resource.clone()), "combat", 100);
+        planner.add_system(components::map::BuilderSystem::new(events.clone(), descend_rx), "map_build", 20);
+        planner.add_system(systems::fov::FovSystem::new(), "fov", 15);
+        planner.add_system(systems::action::ActionSystem::new(events.clone(), action_rx, turn_end_tx), "actions", 1);
+        planner.add_system(systems::ai::DeadSystem::new(events.clone(), transitions.clone()), "dead", 1);
+        planner.add_system(systems::combat::ExplosionSystem::new(events.clone()), "explosions", 100);
+        planner.add_system(systems::combat::CombatSystem::new(events.clone()), "combat", 100);
+        planner.add_system(systems::noise::NoiseSystem::new(), "noise", 50);
         planner.add_system(systems::ui::InfoPanelSystem::new(), "info_panel", 1);
 
         // Add default entities
         let mut camera = components::camera::Camera::new((MAP_WIDTH, MAP_HEIGHT), (100, 100));
         camera.center_on(50, 50);
-        planner.mut_world().create_now()
+        let builder = planner.mut_world().create_now()
             .with(camera)
             .with(components::map::MapRender::new(Window::new(Point::new(1, 1), MAP_WIDTH, MAP_HEIGHT)))
-            .with(components::drawable::DrawableRender::new(Overlay::new(Point::new(1, 1), MAP_WIDTH, MAP_HEIGHT)))
-            .with(components::map::MapBuilder::new());
+            .with(components::drawable::DrawableRender::new(Overlay::new(Point::new(1, 1), MAP_WIDTH, MAP_HEIGHT)));
+
+        // Only dig a new map if there's no saved one
+        if let Some(game) = saved {
+            builder.build();
+            game.restore(planner.mut_world());
+        }
+        else {
+            builder.with(components::map::MapBuilder::new()).build();
+        }
 
         GameScreen {
             sub_screen: vec![SubGameScreen::Map],
             map_frame: map_frame,
             msg_frame: msg_frame,
+            history_frame: history_frame,
             event_channel: event_channel,
             sub_screen_channel: sub_screen_channel,
         }
     }
+}
+
+impl super::Screen for GameScreen {
+    fn setup(planner: &mut specs::Planner<()>, transitions: super::TransitionChannel) -> GameScreen {
+        GameScreen::new(planner, transitions, None)
+    }
 
     fn dispatch(&mut self, event: termion::event::Event) {
-        self.event_channel.send(match event {
+        self.send(match event {
             termion::event::Event::Key(k) => components::input::Event::Key(k),
             termion::event::Event::Mouse(termion::event::MouseEvent::Hold(x, y)) => {
                 // Convert to zero-based
@@ -135,12 +193,26 @@ impl super::Screen for GameScreen {
                 }
             },
             _ => return,
-        }).unwrap();
+        });
     }
 
     fn render(&mut self, planner: &mut specs::Planner<()>, compositor: &mut Compositor) {
         super::SubScreenEvent::apply_all(&self.sub_screen_channel, &mut self.sub_screen);
 
+        // The history covers everything else
+        if let Some(&SubGameScreen::History) = self.sub_screen.last() {
+            let history = planner.mut_world().read_resource::<systems::ui::HistoryPanelResource>();
+            let title = match history.filter {
+                Some(kind) => format!("MESSAGES ({})", kind.name().to_uppercase()),
+                None => "MESSAGES".into(),
+            };
+            self.history_frame.border();
+            self.history_frame.print_at(Point::new(1, 0), &title);
+            self.history_frame.refresh(compositor);
+            history.window.refresh(compositor);
+            return;
+        }
+
         self.map_frame.refresh(compositor);
         let world = planner.mut_world();
         let info = world.read_resource::<systems::ui::InfoPanelResource>();
@@ -170,6 +242,8 @@ impl super::Screen for GameScreen {
                 self.msg_frame.refresh(compositor);
                 res.window.refresh(compositor);
             }
+            // Drawn instead of everything else, above
+            Some(&SubGameScreen::History) => unreachable!(),
         }
 
         for map in maps.iter() {
@@ -46,7 +46,10 @@ pub enum State {
 #[derive(Clone,Copy,Debug,Eq,PartialEq)]
 pub enum StateTransition {
     Game,
+    /// Resume the game in the save file.
+    Continue,
     GameOver,
+    SaveAndQuit,
     Quit,
 }
 
@@ -56,8 +59,9 @@ impl StateTransition {
 
         match *self {
             Game => State::Game(GameScreen::setup(planner, transitions)),
+            Continue => State::Game(GameScreen::resume(planner, transitions)),
             GameOver => State::GameOver(GameOverScreen::setup(planner, transitions)),
-            Quit => panic!("Shouldn't construct this state"),
+            SaveAndQuit | Quit => panic!("Shouldn't construct this state"),
         }
     }
 }
@@ -78,8 +82,16 @@ impl StateManager {
 
     pub fn update(&mut self, planner: &mut specs::Planner<()>) -> bool {
         if let Some(transition) = self.transitions.1.try_iter().last() {
+            if transition == StateTransition::SaveAndQuit {
+                let world = planner.mut_world();
+                if let Err(e) = ::save::save(world, ::save::SAVE_PATH) {
+                    world.read_resource::<::events::EventBus>().notice(format!("Couldn't save: {}", e));
+                    return false;
+                }
+            }
+
             self.teardown(planner);
-            if transition == StateTransition::Quit {
+            if transition == StateTransition::Quit || transition == StateTransition::SaveAndQuit {
                 return true;
             }
             self.state = transition.make(planner, self.transitions.0.clone());
@@ -0,0 +1,562 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::mpsc;
+
+use specs::{self, Join};
+
+use ::components::{ai, combat, health, map, noise, player, position, status, turn};
+use ::components::action::{Action, ActionError, ActionResult};
+use ::events::{AttackOutcome, EventBus, GameEvent, Who};
+use ::util::{self, Direction, HasStorage};
+use ::util::combat::CombatResult;
+use ::util::rng::Rngs;
+use super::ai::{Agent, AiSystem};
+
+/// Runs the turn scheduler: carries out the player's action, then
+/// lets everyone else choose and take theirs until it's the player's
+/// turn again.
+pub struct ActionSystem {
+    events: EventBus,
+    ai: AiSystem,
+    player_actions: mpsc::Receiver<Action>,
+    turn_end: mpsc::Sender<()>,
+}
+
+/// The cell a step in `direction` from `position` leads to, unless
+/// that's off the map or cuts the corner of a wall.
+fn neighbour(map: &map::Map, position: &position::Position, direction: Direction) -> Option<(usize, usize)> {
+    let (dx, dy) = direction.offset();
+    let (x, y) = (position.x as i32 + dx, position.y as i32 + dy);
+    if x < 0 || y < 0 || x as usize >= map.width || y as usize >= map.height {
+        return None;
+    }
+    let (x, y) = (x as usize, y as usize);
+    if map.cuts_corner((position.x, position.y), (x, y)) {
+        return None;
+    }
+    Some((x, y))
+}
+
+/// Recompute the health boost and damage reduction given by the
+/// armor worn.
+fn refit(equip: &player::Equip, dr: &mut combat::DamageReduction, health: &mut health::Health) {
+    *dr = combat::DamageReduction::new();
+    health.max_health = health.base_health;
+    for item in equip.list_equipped() {
+        if let player::ItemKind::Armor { health: boost, damage_reduction, resistances } = item.kind {
+            dr.value += damage_reduction;
+            dr.resistances.add(&resistances);
+            health.max_health += boost;
+        }
+    }
+}
+
+impl ActionSystem {
+    pub fn new(events: EventBus, player_actions: mpsc::Receiver<Action>, turn_end: mpsc::Sender<()>) -> ActionSystem {
+        ActionSystem {
+            ai: AiSystem::new(events.clone()),
+            events: events,
+            player_actions: player_actions,
+            turn_end: turn_end,
+        }
+    }
+
+    /// Pick up the item lying at `(x, y)`, if there is one, returning
+    /// the entity it was lying there as.
+    fn pick_up<G>(&self, who: Who, map: &mut map::Map, grabbables: &G, inventory: &mut player::Inventory,
+                  x: usize, y: usize) -> Option<specs::Entity>
+        where G: HasStorage<player::Grabbable> {
+        let entity = match map.contents(x, y) {
+            Some(entity) => entity,
+            None => return None,
+        };
+        match grabbables.get(entity) {
+            Some(&player::Grabbable(ref item)) => {
+                inventory.add(item.clone());
+                self.events.publish(GameEvent::ItemPicked { who: who, item: item.name.clone() });
+                map.vacate(x, y);
+                Some(entity)
+            }
+            None => None,
+        }
+    }
+
+    /// Tell the player why their action couldn't be taken.
+    fn report(&self, error: ActionError) {
+        let event = match error {
+            ActionError::NothingEquipped => GameEvent::AttackResolved { who: Who::Player, outcome: AttackOutcome::NothingEquipped },
+            ActionError::OutOfRange => GameEvent::AttackResolved { who: Who::Player, outcome: AttackOutcome::OutOfRange },
+            ActionError::OutOfAmmo => GameEvent::AttackResolved { who: Who::Player, outcome: AttackOutcome::OutOfAmmo },
+            ActionError::NoTarget => GameEvent::AttackResolved { who: Who::Player, outcome: AttackOutcome::NoTarget },
+            ActionError::CannotReload(reason) => GameEvent::ReloadFailed(reason),
+            ActionError::FullHealth => GameEvent::Notice("You're already at full health.".into()),
+            // Bumping into things needs no explanation, and equipping
+            // says itself what can't be equipped
+            _ => return,
+        };
+        self.events.publish(event);
+    }
+}
+
+impl specs::System<()> for ActionSystem {
+    fn run(&mut self, arg: specs::RunArg, _: ()) {
+        let player_action = if let Ok(action) = self.player_actions.try_recv() {
+            action
+        }
+        else {
+            // Required to make specs not panic
+            arg.fetch(|_| {});
+            return;
+        };
+
+        let (mut map, mut rngs, mut scheduler, entities, mut actors, mut behaviors, mut perceptions, mut chasers, mut attacked, mut explosions, mut dead, covers, mut crouching, mut sneaking, mut dr, mut temp_dr, mut healths, mut equipped, mut inventories, grabbables, mut noises, players, mut positions, mut statuses) = arg.fetch(|world| {
+            (
+                world.write_resource::<map::Map>(),
+                world.write_resource::<Rngs>(),
+                world.write_resource::<turn::Scheduler>(),
+                world.entities(),
+                world.write::<turn::Actor>(),
+                world.write::<ai::Behavior>(),
+                world.write::<ai::Perception>(),
+                world.write::<ai::ChaseBehavior>(),
+                world.write::<combat::Attacked>(),
+                world.write::<combat::Explosion>(),
+                world.write::<ai::Dead>(),
+                world.read::<health::Cover>(),
+                world.write::<combat::Crouching>(),
+                world.write::<noise::Sneaking>(),
+                world.write::<combat::DamageReduction>(),
+                world.write::<combat::TemporaryDamageReduction>(),
+                world.write::<health::Health>(),
+                world.write::<player::Equip>(),
+                world.write::<player::Inventory>(),
+                world.read::<player::Grabbable>(),
+                world.write::<noise::Noise>(),
+                world.read::<player::Player>(),
+                world.write::<position::Position>(),
+                world.write::<status::StatusEffects>(),
+            )
+        });
+
+        // Nobody gets to act once the player is dead
+        let player = match (&entities, &players, !&dead).iter().next() {
+            Some((entity, _, _)) => entity,
+            None => {
+                self.turn_end.send(()).unwrap();
+                return;
+            }
+        };
+
+        let mut pending = Some(player_action);
+        for _ in 0..turn::MAX_ACTIONS {
+            let (me, action, alert) = if let Some(action) = pending.take() {
+                (player, action, None)
+            }
+            else {
+                let next = {
+                    let mut ready: Vec<_> = (&entities, &mut actors, !&dead).iter()
+                        .map(|(entity, actor, _)| (entity, actor, players.get(entity).is_some()))
+                        .collect();
+                    scheduler.next(&mut ready)
+                };
+
+                let me = match next {
+                    Some(entity) => entity,
+                    // Nobody can act
+                    None => break,
+                };
+                let is_player = me == player;
+
+                // Status effects take hold at the start of each turn,
+                // including the player's
+                if let Some(tick) = statuses.get_mut(me).map(|effects| effects.tick()) {
+                    if tick.damage > 0 {
+                        if let Some(health) = healths.get_mut(me) {
+                            let damage = ::std::cmp::min(tick.damage, health.health);
+                            health.health -= damage;
+                            self.events.publish(GameEvent::StatusDamage {
+                                who: if is_player { Who::Player } else { Who::Enemy },
+                                damage: damage,
+                                left: health.health,
+                            });
+
+                            if health.health == 0 {
+                                dead.insert(me, ai::Dead);
+                                if is_player {
+                                    break;
+                                }
+                                continue;
+                            }
+                        }
+                    }
+
+                    if tick.stunned {
+                        self.events.publish(GameEvent::Stunned { who: if is_player { Who::Player } else { Who::Enemy } });
+                        if let Some(actor) = actors.get_mut(me) {
+                            actor.spend(turn::WAIT_COST);
+                        }
+                        continue;
+                    }
+                }
+
+                if is_player {
+                    break;
+                }
+
+                let player_position = positions.get(player).map_or((0, 0), |pos| (pos.x, pos.y));
+                match (chasers.get_mut(me), behaviors.get_mut(me), perceptions.get_mut(me),
+                       positions.get(me), equipped.get(me)) {
+                    (Some(chaser), Some(behavior), Some(perception), Some(position), Some(equip)) => {
+                        let mut agent = Agent {
+                            me: me,
+                            chaser: chaser,
+                            behavior: behavior,
+                            perception: perception,
+                            position: position,
+                            equip: equip,
+                            inventory: inventories.get(me),
+                            map: &map,
+                            player_position: player_position,
+                            sees_target: false,
+                            healths: &healths,
+                            covers: &covers,
+                            rng: &mut rngs.ai,
+                            alert: None,
+                        };
+                        let action = self.ai.choose(&mut agent);
+                        (me, action, agent.alert)
+                    }
+                    _ => (me, Action::Wait, None),
+                }
+            };
+
+            let is_player = me == player;
+            let who = if is_player { Who::Player } else { Who::Enemy };
+            let here = positions.get(me).cloned();
+
+            let result: ActionResult = match action {
+                Action::Move(direction) => {
+                    let target = here.and_then(|here| neighbour(&map, &here, direction))
+                        .map(|(x, y)| (x, y, map.cell(x, y)));
+                    match target {
+                        None => Err(ActionError::Blocked),
+
+                        // Bumping into a door opens it, or kicks at it
+                        // if it's locked
+                        Some((x, y, map::MapCell::Door(map::DoorState::Closed))) => {
+                            map.open_door(x, y);
+                            self.events.publish(GameEvent::DoorOpened { who: who });
+                            noises.insert(arg.create(), noise::Noise::new(position::Position::new(x, y), noise::DOOR_NOISE, Some(me)));
+                            Ok(turn::MOVE_COST)
+                        }
+                        Some((x, y, map::MapCell::Door(map::DoorState::Locked))) => {
+                            let broken = map.damage_terrain(x, y, 1);
+                            self.events.publish(GameEvent::DoorKicked { who: who, broken: broken });
+                            noises.insert(arg.create(), noise::Noise::new(position::Position::new(x, y), noise::KICK_NOISE, Some(me)));
+                            Ok(turn::MOVE_COST)
+                        }
+
+                        Some((x, y, _)) => {
+                            let sneaky = sneaking.get(me).is_some();
+                            let mut cost = if sneaky { turn::SNEAK_COST } else { turn::MOVE_COST };
+                            if let Some(inventory) = inventories.get_mut(me) {
+                                if let Some(item) = self.pick_up(who, &mut map, &grabbables, inventory, x, y) {
+                                    positions.remove(item);
+                                    arg.delete(item);
+                                    cost += turn::PICK_UP_COST;
+                                }
+                            }
+
+                            let moved = match positions.get_mut(me) {
+                                Some(position) => position.move_to(x, y, &mut map).is_ok(),
+                                None => false,
+                            };
+                            if moved {
+                                // Moving stands you up
+                                crouching.remove(me);
+                                let loudness = if sneaky { noise::SNEAK_NOISE } else { noise::MOVE_NOISE };
+                                noises.insert(arg.create(), noise::Noise::new(position::Position::new(x, y), loudness, Some(me)));
+                                Ok(cost)
+                            }
+                            else {
+                                Err(ActionError::Blocked)
+                            }
+                        }
+                    }
+                }
+
+                Action::PickUp(direction) => {
+                    let target = here.and_then(|here| neighbour(&map, &here, direction));
+                    match (target, inventories.get_mut(me)) {
+                        (None, _) => Err(ActionError::NothingThere),
+                        (_, None) => Err(ActionError::NoInventory),
+                        (Some((x, y)), Some(inventory)) => match self.pick_up(who, &mut map, &grabbables, inventory, x, y) {
+                            Some(item) => {
+                                positions.remove(item);
+                                arg.delete(item);
+                                Ok(turn::PICK_UP_COST)
+                            }
+                            None => Err(ActionError::NothingThere),
+                        },
+                    }
+                }
+
+                Action::Attack { target, melee } => match (here, equipped.get_mut(me)) {
+                    (Some(origin), Some(equip)) => {
+                        let cost = equip.attack_cost(melee);
+                        let loudness = equip.noise(melee);
+                        let result = util::combat::resolve(
+                            &map, me, equip, origin, target, melee,
+                            &healths, &covers, &statuses, &crouching, &mut rngs.combat);
+                        let outcome = match result {
+                            // Nothing was fired, so no time passes
+                            CombatResult::NothingEquipped => Err(ActionError::NothingEquipped),
+                            CombatResult::OutOfRange => Err(ActionError::OutOfRange),
+                            CombatResult::OutOfAmmo => Err(ActionError::OutOfAmmo),
+                            CombatResult::NoTarget => Err(ActionError::NoTarget),
+                            CombatResult::Miss => Ok(AttackOutcome::Miss),
+                            CombatResult::HitNothing => Ok(AttackOutcome::HitNothing),
+                            CombatResult::HitEnvironment(pos, attack) => {
+                                let name = map.cell(pos.x, pos.y).name();
+                                let breached = util::combat::breach(&mut map, pos, &attack, &mut rngs.combat);
+                                Ok(AttackOutcome::HitTerrain { name: name, breached: breached })
+                            }
+                            CombatResult::HitEntity(victim, pos, attack) => {
+                                combat::Attacked::push(&mut attacked, victim, attack);
+                                Ok(AttackOutcome::HitEntity(pos))
+                            }
+                            CombatResult::Explode(explosion) => {
+                                let center = explosion.center;
+                                explosions.insert(arg.create(), explosion);
+                                Ok(AttackOutcome::Explosive(center))
+                            }
+                        };
+                        outcome.map(|outcome| {
+                            noises.insert(arg.create(), noise::Noise::new(origin, loudness, Some(me)));
+                            self.events.publish(GameEvent::AttackResolved { who: who, outcome: outcome });
+                            cost
+                        })
+                    }
+                    _ => Err(ActionError::NothingEquipped),
+                },
+
+                Action::Use { index, target } => {
+                    use ::components::player::{Effect, Item, ItemKind};
+
+                    let effect = match inventories.get(me).and_then(|inventory| inventory.contents.get(index)) {
+                        Some(&Item { kind: ItemKind::Consumable(effect), .. }) => Ok(effect),
+                        Some(_) => Err(ActionError::CannotUse),
+                        None => Err(ActionError::NoSuchItem),
+                    };
+                    let used = match effect {
+                        Err(error) => Err(error),
+
+                        Ok(Effect::Explode { damage, radius, range }) => match (here, target) {
+                            (Some(origin), Some(target)) => {
+                                let landing = util::combat::landing_point(&map, origin, target, range);
+                                explosions.insert(arg.create(), combat::Explosion::new(landing, radius, combat::Attack {
+                                    damage: damage,
+                                    // Fragments
+                                    damage_type: combat::DamageType::Ballistic,
+                                    accuracy: 1000,
+                                    penetration: 0,
+                                    crit_chance: 0,
+                                    crit_multiplier: 100,
+                                    source: me,
+                                    status: None,
+                                    breach: true,
+                                }));
+                                Ok(())
+                            }
+                            _ => Err(ActionError::CannotUse),
+                        },
+
+                        Ok(Effect::Heal(amount)) => {
+                            let healed = healths.get_mut(me).map_or(0, |health| {
+                                let healed = ::std::cmp::min(amount, health.max_health.saturating_sub(health.health));
+                                health.health += healed;
+                                healed
+                            });
+                            if healed == 0 {
+                                Err(ActionError::FullHealth)
+                            }
+                            else {
+                                if is_player {
+                                    self.events.publish(GameEvent::Healed { amount: healed });
+                                }
+                                Ok(())
+                            }
+                        }
+
+                        Ok(Effect::Shield { damage_reduction, turns }) => {
+                            temp_dr.insert(me, combat::TemporaryDamageReduction {
+                                value: damage_reduction,
+                                expires: scheduler.turns + turns as u64,
+                            });
+                            if is_player {
+                                self.events.publish(GameEvent::Shielded { damage_reduction: damage_reduction, turns: turns });
+                            }
+                            Ok(())
+                        }
+                    };
+
+                    used.map(|_| {
+                        let item = inventories.get_mut(me).and_then(|inventory| inventory.take(index));
+                        if let (Some(Effect::Explode { .. }), Some(item)) = (effect.ok(), item) {
+                            if is_player {
+                                self.events.publish(GameEvent::Thrown { item: item.name });
+                            }
+                        }
+                        turn::USE_COST
+                    })
+                }
+
+                Action::Equip(index) => {
+                    let item = inventories.get(me).and_then(|inventory| inventory.contents.get(index).cloned());
+                    match (item, equipped.get_mut(me)) {
+                        (None, _) => Err(ActionError::NoSuchItem),
+                        (Some(item), Some(equip)) => {
+                            if item.slot.is_none() {
+                                if is_player {
+                                    self.events.publish(GameEvent::CannotEquip { item: item.name });
+                                }
+                                Err(ActionError::CannotEquip)
+                            }
+                            else {
+                                let name = item.name.clone();
+                                let old = equip.equip(item);
+                                // The item taken off goes where the
+                                // new one was
+                                if let Some(inventory) = inventories.get_mut(me) {
+                                    match old {
+                                        Some(old) => inventory.contents[index] = old,
+                                        None => {
+                                            inventory.contents.remove(index);
+                                        }
+                                    }
+                                }
+
+                                let mut reduction = 0;
+                                if let (Some(armor), Some(health)) = (dr.get_mut(me), healths.get_mut(me)) {
+                                    refit(equip, armor, health);
+                                    reduction = armor.value;
+                                }
+                                if is_player {
+                                    self.events.publish(GameEvent::ItemEquipped { item: name, damage_reduction: reduction });
+                                }
+                                Ok(turn::EQUIP_COST)
+                            }
+                        }
+                        (Some(_), None) => Err(ActionError::CannotEquip),
+                    }
+                }
+
+                Action::Unequip(slot) => match (equipped.get_mut(me), inventories.get_mut(me)) {
+                    (Some(equip), Some(inventory)) => match equip.unequip(slot) {
+                        Some(item) => {
+                            inventory.add(item);
+                            if let (Some(armor), Some(health)) = (dr.get_mut(me), healths.get_mut(me)) {
+                                refit(equip, armor, health);
+                            }
+                            Ok(turn::EQUIP_COST)
+                        }
+                        None => Err(ActionError::NothingEquipped),
+                    },
+                    (_, None) => Err(ActionError::NoInventory),
+                    (None, _) => Err(ActionError::NothingEquipped),
+                },
+
+                Action::Reload => {
+                    let reload = match (equipped.get_mut(me), inventories.get_mut(me)) {
+                        (Some(equip), Some(inventory)) => equip.reload(inventory),
+                        _ => player::Reload::NotReloadable,
+                    };
+                    match reload {
+                        player::Reload::Reloaded(loaded) => {
+                            self.events.publish(GameEvent::Reloaded { who: who, loaded: loaded });
+                            Ok(turn::RELOAD_COST)
+                        }
+                        failed => Err(ActionError::CannotReload(failed)),
+                    }
+                }
+
+                Action::Crouch => {
+                    let crouched = if crouching.remove(me).is_some() {
+                        false
+                    }
+                    else {
+                        crouching.insert(me, combat::Crouching);
+                        true
+                    };
+                    if is_player {
+                        self.events.publish(GameEvent::Crouched(crouched));
+                    }
+                    Ok(turn::CROUCH_COST)
+                }
+
+                // Takes effect from the next step, so it's free
+                Action::Sneak => {
+                    let sneaky = if sneaking.remove(me).is_some() {
+                        false
+                    }
+                    else {
+                        sneaking.insert(me, noise::Sneaking);
+                        true
+                    };
+                    if is_player {
+                        self.events.publish(GameEvent::Sneaking(sneaky));
+                    }
+                    Ok(0)
+                }
+
+                Action::Wait => Ok(turn::WAIT_COST),
+            };
+
+            let cost = match result {
+                Ok(cost) => cost,
+                // Nothing happened, so the player can try something
+                // else
+                Err(error) if is_player => {
+                    self.report(error);
+                    0
+                }
+                Err(_) => turn::WAIT_COST,
+            };
+            if is_player && cost == 0 {
+                self.turn_end.send(()).unwrap();
+                return;
+            }
+
+            // Everyone in earshot comes running
+            if let Some(target) = alert {
+                let caller = here.map_or(target, |pos| (pos.x, pos.y));
+                let radius = ai::HELP_RADIUS;
+                for (ally, chaser, pos, _) in (&entities, &mut chasers, &positions, !&dead).iter() {
+                    if ally != me && util::distance2(caller, (pos.x, pos.y)) <= radius * radius {
+                        chaser.spot(target);
+                    }
+                }
+            }
+
+            if let Some(actor) = actors.get_mut(me) {
+                actor.spend(cost);
+            }
+        }
+
+        scheduler.turns += 1;
+        self.turn_end.send(()).unwrap();
+    }
+}
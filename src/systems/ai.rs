@@ -13,129 +13,485 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::sync::mpsc;
-
-use rand::{self, Rng};
+use rand::Rng;
 use specs::{self, Join};
 
-use ::components::{ai, combat, drawable, health, map, player, position};
-use ::components::input::OffsetMovable;
-use ::util;
+use ::components::{ai, drawable, health, map, player, position};
+use ::components::action::Action;
+use ::events::{EventBus, GameEvent, Who};
+use ::util::{self, HasStorage};
+use ::util::combat::CoverLevel;
+use ::util::rng::Rngs;
 
+/// Decides what AI-controlled entities do with their turns. The
+/// `ActionSystem` carries the actions out.
 pub struct AiSystem {
-    message_queue: mpsc::Sender<String>,
-    ai_begin: mpsc::Receiver<()>,
-    ai_end: mpsc::Sender<()>,
+    events: EventBus,
 }
 
 pub struct DeadSystem {
+    events: EventBus,
     transitions: ::screen::TransitionChannel,
 }
 
+/// Everything an AI-controlled entity works with on its turn.
+pub struct Agent<'a, H: 'a, C: 'a, R: 'a> {
+    pub me: specs::Entity,
+    pub chaser: &'a mut ai::ChaseBehavior,
+    pub behavior: &'a mut ai::Behavior,
+    pub perception: &'a mut ai::Perception,
+    pub position: &'a position::Position,
+    pub equip: &'a player::Equip,
+    pub inventory: Option<&'a player::Inventory>,
+    pub map: &'a map::Map,
+    pub player_position: (usize, usize),
+    /// Whether the player is in sight, and has been noticed, this
+    /// turn.
+    pub sees_target: bool,
+    pub healths: &'a H,
+    pub covers: &'a C,
+    pub rng: &'a mut R,
+    /// Where allies were told the target is, if it called for help.
+    pub alert: Option<(usize, usize)>,
+}
+
+/// The result of running a behavior tree node.
+enum Status {
+    Failure,
+    Success,
+    /// Chose an action. This ends the turn.
+    Acted(Action),
+}
+
 impl AiSystem {
-    pub fn new(message_queue: mpsc::Sender<String>, ai_begin: mpsc::Receiver<()>, ai_end: mpsc::Sender<()>) -> AiSystem {
+    pub fn new(events: EventBus) -> AiSystem {
         AiSystem {
-            message_queue: message_queue,
-            ai_begin: ai_begin,
-            ai_end: ai_end,
+            events: events,
         }
     }
-}
 
-impl specs::System<()> for AiSystem {
-    fn run(&mut self, arg: specs::RunArg, _: ()) {
-        if let Ok(()) = self.ai_begin.try_recv() {
+    /// Decide on one action, waiting if there's nothing to do.
+    pub fn choose<H, C, R>(&self, agent: &mut Agent<H, C, R>) -> Action
+        where H: HasStorage<health::Health>, C: HasStorage<health::Cover>, R: Rng {
+        self.perceive(agent);
 
+        match self.run_node(&agent.behavior.archetype.tree(), agent) {
+            Status::Acted(action) => action,
+            Status::Success | Status::Failure => Action::Wait,
         }
-        else {
-            // Required to make specs not panic
-            arg.fetch(|_| {});
-            return;
+    }
+
+    /// Look for the target, updating how aware of it we are.
+    fn perceive<H, C, R>(&self, agent: &mut Agent<H, C, R>) {
+        let here = (agent.position.x, agent.position.y);
+        let target = agent.player_position;
+        let perception = &mut *agent.perception;
+
+        // Getting shot at, hearing something or being called for help
+        // puts us on alert
+        if agent.chaser.spotted.is_some() && perception.state != ai::Awareness::Alert {
+            perception.state = ai::Awareness::Alert;
+            perception.awareness = ai::AWARENESS_MAX;
         }
 
-        let (mut map, entities, mut chase_behaviors, mut attacked, dead, covers, healths, equipped, players, mut positions) = arg.fetch(|world| {
-            (
-                world.write_resource::<map::Map>(),
-                world.entities(),
-                world.write::<ai::ChaseBehavior>(),
-                world.write::<combat::Attack>(),
-                world.read::<ai::Dead>(),
-                world.read::<health::Cover>(),
-                world.read::<health::Health>(),
-                world.read::<player::Equip>(),
-                world.read::<player::Player>(),
-                world.write::<position::Position>(),
-            )
-        });
+        let visible = perception.in_view(here, target) && agent.map.line_of_sight(here, target);
+        if visible {
+            // Closer is easier to make out
+            let steps = util::chebyshev(here, target);
+            let gain = ai::AWARENESS_MAX * 2 / (steps + 1);
+            perception.awareness = if perception.state == ai::Awareness::Searching {
+                ai::AWARENESS_MAX
+            }
+            else {
+                ::std::cmp::min(ai::AWARENESS_MAX, perception.awareness + gain)
+            };
+            perception.face(here, target);
+            perception.last_seen = Some(target);
 
-        let mut player_position = (0, 0);
-        for (_, position) in (&players, &positions).iter() {
-            player_position = (position.x, position.y);
-        }
-
-        for (me, chaser, position, equip, _) in (&entities, &mut chase_behaviors, &mut positions, &equipped, !&dead).iter() {
-            if util::distance2(player_position, (position.x, position.y)) < 25 {
-                chaser.spotted = Some(player_position);
-            }
-
-            if let Some((x, y)) = chaser.spotted {
-
-                if util::distance2((x, y), (position.x, position.y)) < 9 {
-                    if let Some(_) = map.contents(x, y) {
-                        match ::util::combat::resolve(
-                            &map, me, &equip, *position,
-                            position::Position::new(player_position.0, player_position.1),
-                            false, &healths, &covers) {
-                            ::util::combat::CombatResult::NothingEquipped => {
-                            }
-                            ::util::combat::CombatResult::Miss => {
-                                self.message_queue.send("Enemy missed!".into()).unwrap();
-                            }
-                            ::util::combat::CombatResult::HitNothing => {
-                                self.message_queue.send("Enemy hit nothing.".into()).unwrap();
-                            }
-                            ::util::combat::CombatResult::HitEnvironment => {
-                                self.message_queue.send("Enemy hit a wall.".into()).unwrap();
-                            }
-                            ::util::combat::CombatResult::HitEntity(target, pos, attack) => {
-                                self.message_queue.send(format!("Enemy targeted {}, {}", pos.x, pos.y)).unwrap();
-                                attacked.insert(target, attack);
-                            }
-                            ::util::combat::CombatResult::OutOfRange => {
-                                self.message_queue.send("Enemy tried a melee weapon out of range.".into()).unwrap();
-                            }
-                        }
-                        continue;
-                    }
+            if perception.awareness >= ai::AWARENESS_MAX {
+                if perception.state == ai::Awareness::Unaware || perception.state == ai::Awareness::Suspicious {
+                    self.events.publish(GameEvent::Spotted);
                 }
+                perception.state = ai::Awareness::Alert;
+                agent.chaser.spot(target);
+                agent.sees_target = true;
+            }
+            else if perception.state == ai::Awareness::Unaware {
+                perception.state = ai::Awareness::Suspicious;
+            }
+            return;
+        }
 
-                let dx = (x as i32 - position.x as i32).signum();
-                let dy = (y as i32 - position.y as i32).signum();
+        perception.awareness = perception.awareness.saturating_sub(ai::AWARENESS_DECAY);
+        match perception.state {
+            ai::Awareness::Alert => {
+                // Reached where the target was, and it's gone
+                if agent.chaser.spotted.is_none() {
+                    perception.state = ai::Awareness::Searching;
+                    perception.search_turns = ai::SEARCH_TURNS;
+                    agent.behavior.goal = None;
+                }
+            }
+            ai::Awareness::Searching => {
+                perception.search_turns = perception.search_turns.saturating_sub(1);
+                if perception.search_turns == 0 {
+                    perception.state = ai::Awareness::Unaware;
+                    perception.awareness = 0;
+                    agent.behavior.goal = None;
+                }
+            }
+            ai::Awareness::Suspicious => {
+                if perception.awareness == 0 {
+                    perception.state = ai::Awareness::Unaware;
+                }
+                else if let Some(last_seen) = perception.last_seen {
+                    // Keep an eye on where it was
+                    perception.face(here, last_seen);
+                }
+            }
+            ai::Awareness::Unaware => {}
+        }
+    }
 
-                // Move towards the last known player position if
-                // seen, else forget
-                if dx != 0 {
-                    if let Ok(_) = position.move_by((dx, 0), &mut map) {
-                        continue;
+    fn run_node<H, C, R>(&self, node: &ai::Node, agent: &mut Agent<H, C, R>) -> Status
+        where H: HasStorage<health::Health>, C: HasStorage<health::Cover>, R: Rng {
+        match *node {
+            ai::Node::Selector(ref children) => {
+                for child in children.iter() {
+                    match self.run_node(child, agent) {
+                        Status::Failure => continue,
+                        status => return status,
                     }
                 }
-                if dy != 0 {
-                    if let Ok(_) = position.move_by((0, dy), &mut map) {
-                        continue;
+                Status::Failure
+            }
+            ai::Node::Sequence(ref children) => {
+                for child in children.iter() {
+                    match self.run_node(child, agent) {
+                        Status::Success => continue,
+                        status => return status,
                     }
                 }
+                Status::Success
+            }
+            ai::Node::Condition(condition) => {
+                if self.check(condition, agent) {
+                    Status::Success
+                }
+                else {
+                    Status::Failure
+                }
+            }
+            ai::Node::Task(task) => self.perform(task, agent),
+        }
+    }
+
+    fn check<H, C, R>(&self, condition: ai::Condition, agent: &mut Agent<H, C, R>) -> bool
+        where H: HasStorage<health::Health> {
+        match condition {
+            ai::Condition::HealthBelow(percent) => agent.healths.get(agent.me).map_or(false, |health| {
+                health.health * 100 < health.max_health * percent
+            }),
+            ai::Condition::SeesTarget => agent.sees_target,
+            ai::Condition::Alerted => agent.chaser.spotted.is_some(),
+        }
+    }
+
+    fn perform<H, C, R>(&self, task: ai::Task, agent: &mut Agent<H, C, R>) -> Status
+        where H: HasStorage<health::Health>, C: HasStorage<health::Cover>, R: Rng {
+        let here = (agent.position.x, agent.position.y);
+
+        match task {
+            ai::Task::Attack => self.attack(agent),
+
+            ai::Task::Reload => {
+                if agent.equip.can_fire() {
+                    return Status::Failure;
+                }
+                match agent.inventory {
+                    Some(inventory) if agent.equip.can_reload(inventory) => Status::Acted(Action::Reload),
+                    _ => Status::Failure,
+                }
+            }
+
+            ai::Task::Chase => self.chase(agent),
+
+            ai::Task::Flee => {
+                let threat = agent.chaser.spotted.unwrap_or(agent.player_position);
+                self.flee(agent, threat)
+            }
+
+            ai::Task::SeekCover => self.seek_cover(agent),
+
+            ai::Task::KeepRange(steps) => {
+                if util::chebyshev(here, agent.player_position) < steps {
+                    let threat = agent.player_position;
+                    self.flee(agent, threat)
+                }
+                else {
+                    Status::Failure
+                }
+            }
 
-                chaser.spotted = None;
+            ai::Task::CallForHelp => {
+                if agent.behavior.called {
+                    return Status::Failure;
+                }
+                agent.behavior.called = true;
+                agent.alert = agent.chaser.spotted;
+                self.events.publish(GameEvent::CalledForHelp);
+                Status::Acted(Action::Wait)
+            }
+
+            ai::Task::Patrol => {
+                let home = agent.behavior.home;
+                self.wander(agent, home, ai::PATROL_RADIUS)
+            }
+
+            ai::Task::Search => {
+                if agent.perception.state != ai::Awareness::Searching {
+                    return Status::Failure;
+                }
+                let center = agent.perception.last_seen.unwrap_or(here);
+                self.wander(agent, center, ai::SEARCH_RADIUS)
+            }
+
+            ai::Task::Guard => {
+                let home = agent.behavior.home;
+                if here == home {
+                    Status::Acted(Action::Wait)
+                }
+                else {
+                    self.step_towards(agent, home)
+                }
+            }
+        }
+    }
+
+    fn attack<H, C, R>(&self, agent: &mut Agent<H, C, R>) -> Status
+        where H: HasStorage<health::Health>, C: HasStorage<health::Cover>, R: Rng {
+        if !agent.sees_target {
+            return Status::Failure;
+        }
+
+        let here = (agent.position.x, agent.position.y);
+        // Up close, use a melee weapon if there is one
+        let is_melee = util::chebyshev(here, agent.player_position) <= 1 && agent.equip.secondary.is_some();
+        if !is_melee {
+            // Out of ammo enemies still give chase
+            let range = agent.equip.range(false);
+            if !agent.equip.can_fire() || range == 0 ||
+                util::distance2(here, agent.player_position) > range * range {
+                return Status::Failure;
             }
         }
 
-        self.ai_end.send(()).unwrap();
+        let target = position::Position::new(agent.player_position.0, agent.player_position.1);
+        Status::Acted(Action::Attack { target: target, melee: is_melee })
+    }
+
+    /// Follow the planned route to where the target was last seen,
+    /// forgetting about it once there.
+    fn chase<H, C, R>(&self, agent: &mut Agent<H, C, R>) -> Status {
+        let (x, y) = match agent.chaser.spotted {
+            Some(spotted) => spotted,
+            None => return Status::Failure,
+        };
+
+        // Got there, and whatever we were chasing is gone
+        if (agent.position.x, agent.position.y) == (x, y) {
+            agent.chaser.forget();
+            agent.behavior.called = false;
+            return Status::Failure;
+        }
+
+        // Made it to the next step last turn
+        if agent.chaser.next_step() == Some((agent.position.x, agent.position.y)) {
+            agent.chaser.path.pop();
+        }
+
+        // Replan if there is no route yet, if something has moved
+        // into the way, or if the terrain has changed
+        let closed = map::MapCell::Door(map::DoorState::Closed);
+        let blocked = match agent.chaser.next_step() {
+            Some((nx, ny)) => !agent.map.passable(nx, ny) && (nx, ny) != (x, y) && agent.map.cell(nx, ny) != closed,
+            None => true,
+        };
+        if blocked || agent.chaser.revision != agent.map.revision {
+            agent.chaser.revision = agent.map.revision;
+            let route = {
+                let map = &*agent.map;
+                util::path::find_path(
+                    map, (agent.position.x, agent.position.y), (x, y),
+                    |cx, cy| util::path::walk_cost(map, (x, y), cx, cy))
+            };
+            agent.chaser.path = route.map(|mut route| {
+                route.reverse();
+                route
+            }).unwrap_or_else(Vec::new);
+        }
+
+        // Move towards the last known player position if seen, else
+        // forget
+        if let Some((nx, ny)) = agent.chaser.next_step() {
+            if (nx, ny) == (x, y) && !agent.map.passable(nx, ny) {
+                // Whatever we're chasing is right there
+                return Status::Acted(Action::Wait);
+            }
+            if let Status::Acted(action) = self.step(agent, nx, ny) {
+                return Status::Acted(action);
+            }
+        }
+
+        agent.chaser.forget();
+        agent.behavior.called = false;
+        Status::Failure
+    }
+
+    /// Step to the neighbouring cell furthest from `threat`, if that
+    /// gets any further away.
+    fn flee<H, C, R>(&self, agent: &mut Agent<H, C, R>, threat: (usize, usize)) -> Status {
+        let here = (agent.position.x, agent.position.y);
+        let mut best = (util::distance2(here, threat), None);
+        for direction in util::DIRECTIONS.iter() {
+            let (dx, dy) = direction.offset();
+            let (x, y) = (here.0 as i32 + dx, here.1 as i32 + dy);
+            if x < 0 || y < 0 {
+                continue;
+            }
+            let (x, y) = (x as usize, y as usize);
+            if !agent.map.passable(x, y) || agent.map.cuts_corner(here, (x, y)) {
+                continue;
+            }
+            let distance = util::distance2((x, y), threat);
+            if distance > best.0 {
+                best = (distance, Some((x, y)));
+            }
+        }
+
+        match best.1 {
+            Some((x, y)) => self.step(agent, x, y),
+            None => Status::Failure,
+        }
+    }
+
+    /// Head for the nearest cell with cover from the target, failing
+    /// if already in cover or if there's none nearby.
+    fn seek_cover<H, C, R>(&self, agent: &mut Agent<H, C, R>) -> Status
+        where C: HasStorage<health::Cover> {
+        let threat = match agent.chaser.spotted {
+            Some(threat) => position::Position::new(threat.0, threat.1),
+            None => return Status::Failure,
+        };
+        let here = (agent.position.x, agent.position.y);
+        let covers = agent.covers;
+        let covered = |map: &map::Map, x: usize, y: usize| {
+            ::util::combat::cover_against(map, covers, threat, position::Position::new(x, y)).0 != CoverLevel::None
+        };
+        if covered(agent.map, here.0, here.1) {
+            return Status::Failure;
+        }
+
+        let radius = ai::COVER_SEARCH_RADIUS as i32;
+        let mut best: Option<(usize, (usize, usize))> = None;
+        for dy in -radius..radius + 1 {
+            for dx in -radius..radius + 1 {
+                let (x, y) = (here.0 as i32 + dx, here.1 as i32 + dy);
+                if x < 0 || y < 0 || x as usize >= agent.map.width || y as usize >= agent.map.height {
+                    continue;
+                }
+                let (x, y) = (x as usize, y as usize);
+                if !agent.map.passable(x, y) || !covered(agent.map, x, y) {
+                    continue;
+                }
+                let distance = util::chebyshev(here, (x, y));
+                if best.map_or(true, |(nearest, _)| distance < nearest) {
+                    best = Some((distance, (x, y)));
+                }
+            }
+        }
+
+        match best {
+            Some((_, cell)) => self.step_towards(agent, cell),
+            None => Status::Failure,
+        }
+    }
+
+    /// Head for a free cell around `center`, picking another once
+    /// there.
+    fn wander<H, C, R>(&self, agent: &mut Agent<H, C, R>, center: (usize, usize), radius: usize) -> Status
+        where R: Rng {
+        let here = (agent.position.x, agent.position.y);
+        if agent.behavior.goal.map_or(true, |goal| goal == here) {
+            agent.behavior.goal = self.random_goal(agent, center, radius);
+        }
+        match agent.behavior.goal {
+            Some(goal) => match self.step_towards(agent, goal) {
+                Status::Failure => {
+                    // Try somewhere else next time
+                    agent.behavior.goal = None;
+                    Status::Failure
+                }
+                status => status,
+            },
+            None => Status::Failure,
+        }
+    }
+
+    /// A free cell within `radius` of `center`.
+    fn random_goal<H, C, R>(&self, agent: &mut Agent<H, C, R>, center: (usize, usize), radius: usize) -> Option<(usize, usize)>
+        where R: Rng {
+        let radius = radius as i32;
+        for _ in 0..10 {
+            let x = center.0 as i32 + agent.rng.gen_range(-radius, radius + 1);
+            let y = center.1 as i32 + agent.rng.gen_range(-radius, radius + 1);
+            if x < 0 || y < 0 || x as usize >= agent.map.width || y as usize >= agent.map.height {
+                continue;
+            }
+            if agent.map.passable(x as usize, y as usize) {
+                return Some((x as usize, y as usize));
+            }
+        }
+        None
+    }
+
+    /// Take the first step on the way to `goal`.
+    fn step_towards<H, C, R>(&self, agent: &mut Agent<H, C, R>, goal: (usize, usize)) -> Status {
+        let here = (agent.position.x, agent.position.y);
+        if here == goal {
+            return Status::Failure;
+        }
+        let route = {
+            let map = &*agent.map;
+            util::path::find_path(map, here, goal, |cx, cy| util::path::walk_cost(map, goal, cx, cy))
+        };
+        match route.and_then(|route| route.first().cloned()) {
+            Some((x, y)) => self.step(agent, x, y),
+            None => Status::Failure,
+        }
+    }
+
+    /// Move to a neighbouring cell, opening the door there first if
+    /// there is one.
+    fn step<H, C, R>(&self, agent: &mut Agent<H, C, R>, x: usize, y: usize) -> Status {
+        let here = (agent.position.x, agent.position.y);
+        agent.perception.face(here, (x, y));
+        let closed = map::MapCell::Door(map::DoorState::Closed);
+        if !agent.map.passable(x, y) && agent.map.cell(x, y) != closed {
+            return Status::Failure;
+        }
+        let offset = (x as i32 - here.0 as i32, y as i32 - here.1 as i32);
+        match util::Direction::from_offset(offset) {
+            Some(direction) => Status::Acted(Action::Move(direction)),
+            None => Status::Failure,
+        }
     }
 }
 
 impl DeadSystem {
-    pub fn new(transitions: ::screen::TransitionChannel) -> DeadSystem {
+    pub fn new(events: EventBus, transitions: ::screen::TransitionChannel) -> DeadSystem {
         DeadSystem {
+            events: events,
             transitions: transitions,
         }
     }
@@ -143,11 +499,13 @@ impl DeadSystem {
 
 impl specs::System<()> for DeadSystem {
     fn run(&mut self, arg: specs::RunArg, _: ()) {
-        let (mut map, entities, dead, mut drawables, mut drops_loot, mut grabbables, players, mut positions) = arg.fetch(|world| {
+        let (mut map, mut rngs, entities, dead, covers, mut drawables, mut drops_loot, mut grabbables, players, mut positions) = arg.fetch(|world| {
             (
                 world.write_resource::<map::Map>(),
+                world.write_resource::<Rngs>(),
                 world.entities(),
                 world.read::<ai::Dead>(),
+                world.read::<health::Cover>(),
                 world.write::<drawable::StaticDrawable>(),
                 world.write::<player::DropsLoot>(),
                 world.write::<player::Grabbable>(),
@@ -162,17 +520,27 @@ impl specs::System<()> for DeadSystem {
             arg.delete(entity);
             map.vacate(position.x, position.y);
 
+            let who = if players.get(entity).is_some() {
+                Who::Player
+            }
+            else if covers.get(entity).is_some() {
+                Who::Object
+            }
+            else {
+                Who::Enemy
+            };
+            self.events.publish(GameEvent::EntityKilled { entity: entity, who: who, position: *position });
+
             if let Some(_) = players.get(entity) {
                 self.transitions.send(::screen::StateTransition::GameOver).unwrap();
             }
             else {
                 if let Some(drop_table) = drops_loot.get(entity) {
-                    if rand::thread_rng().gen_range(0, 1000) < drop_table.chance {
-                        if let Some(loot) = rand::thread_rng().choose(&drop_table.items) {
+                    if rngs.loot.gen_range(0, 1000) < drop_table.chance {
+                        if let Some(loot) = rngs.loot.choose(&drop_table.items) {
                             let corpse = arg.create();
-                            drawables.insert(corpse, drawable::StaticDrawable {
-                                tc: '␣'.into(),
-                            });
+                            drawables.insert(corpse, drawable::StaticDrawable::new(
+                                drawable::Glyph::new('␣')));
                             to_create.push((corpse, *position));
                             grabbables.insert(corpse, player::Grabbable(loot.clone()));
                             map.fill(corpse, position.x, position.y);
@@ -187,3 +555,117 @@ impl specs::System<()> for DeadSystem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use specs::{self, Entity};
+
+    use ::components::{ai, map, player, position};
+    use ::components::action::Action;
+    use ::events::EventBus;
+    use ::util::HasStorage;
+    use ::util::rng::GameRng;
+    use super::{Agent, AiSystem};
+
+    /// A storage with nothing in it.
+    struct Empty;
+
+    impl<C> HasStorage<C> for Empty {
+        fn check(&self, _: Entity) -> bool {
+            false
+        }
+
+        fn get(&self, _: Entity) -> Option<&C> {
+            None
+        }
+    }
+
+    /// A grunt on an open floor, with the player far out of sight.
+    struct Grunt {
+        me: Entity,
+        chaser: ai::ChaseBehavior,
+        behavior: ai::Behavior,
+        perception: ai::Perception,
+        position: position::Position,
+        equip: player::Equip,
+        map: map::Map,
+        rng: GameRng,
+    }
+
+    impl Grunt {
+        fn new(x: usize, y: usize) -> Grunt {
+            let mut map = map::Map::new(40, 40);
+            for cy in 0..40 {
+                for cx in 0..40 {
+                    map.set_cell(cx, cy, map::MapCell::Floor);
+                }
+            }
+            let mut world = specs::World::new();
+            let me = world.create_now().build();
+            map.fill(me, x, y);
+
+            Grunt {
+                me: me,
+                chaser: ai::ChaseBehavior::new(),
+                behavior: ai::Behavior::new(ai::Archetype::Grunt, (x, y)),
+                perception: ai::Archetype::Grunt.perception(),
+                position: position::Position::new(x, y),
+                equip: player::Equip::new(),
+                map: map,
+                rng: GameRng::new(1),
+            }
+        }
+
+        fn turn(&mut self, ai: &AiSystem) -> Action {
+            let empty = Empty;
+            let mut agent = Agent {
+                me: self.me,
+                chaser: &mut self.chaser,
+                behavior: &mut self.behavior,
+                perception: &mut self.perception,
+                position: &self.position,
+                equip: &self.equip,
+                inventory: None,
+                map: &self.map,
+                player_position: (39, 39),
+                sees_target: false,
+                healths: &empty,
+                covers: &empty,
+                rng: &mut self.rng,
+                alert: None,
+            };
+            ai.choose(&mut agent)
+        }
+    }
+
+    #[test]
+    fn chaser_stops_at_empty_last_seen_cell() {
+        let ai = AiSystem::new(EventBus::new());
+        let mut grunt = Grunt::new(10, 10);
+        grunt.chaser.spot((10, 10));
+
+        assert!(grunt.turn(&ai) != Action::Wait);
+        assert_eq!(grunt.chaser.spotted, None);
+    }
+
+    #[test]
+    fn alert_searches_then_loses_interest() {
+        let system = AiSystem::new(EventBus::new());
+        let mut grunt = Grunt::new(10, 10);
+        grunt.chaser.spot((10, 10));
+
+        grunt.turn(&system);
+        assert_eq!(grunt.perception.state, ai::Awareness::Alert);
+
+        // Nothing there, so look around for a while
+        grunt.turn(&system);
+        assert_eq!(grunt.perception.state, ai::Awareness::Searching);
+        for _ in 1..ai::SEARCH_TURNS {
+            grunt.turn(&system);
+            assert_eq!(grunt.perception.state, ai::Awareness::Searching);
+        }
+
+        grunt.turn(&system);
+        assert_eq!(grunt.perception.state, ai::Awareness::Unaware);
+    }
+}
@@ -13,69 +13,118 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::sync::mpsc;
-
-use rand::{self, Rng};
+use rand::Rng;
 use specs::{self, Join};
 
-use ::components::{ai, combat, health, player, position};
+use ::components::{ai, combat, health, map, noise, player, position, status, turn};
+use ::events::{EventBus, GameEvent, Who};
+use ::util::rng::Rngs;
 
 pub struct CombatSystem {
-    message_queue: mpsc::Sender<String>,
+    events: EventBus,
+}
+
+/// Sets off explosions, and clears them away once they've been shown.
+pub struct ExplosionSystem {
+    events: EventBus,
 }
 
 impl CombatSystem {
-    pub fn new(message_queue: mpsc::Sender<String>) -> CombatSystem {
+    pub fn new(events: EventBus) -> CombatSystem {
         CombatSystem {
-            message_queue: message_queue,
+            events: events,
         }
     }
 }
 
 impl specs::System<()> for CombatSystem {
     fn run(&mut self, arg: specs::RunArg, _: ()) {
-        let (entities, players, mut chasers, mut dead, mut attacked, dr, mut healths, positions) = arg.fetch(|world| {
+        let (mut rngs, scheduler, entities, players, mut chasers, mut dead, mut attacked, dr, mut temp_dr, covers, mut healths, mut noises, positions, mut statuses) = arg.fetch(|world| {
             (
+                world.write_resource::<Rngs>(),
+                world.read_resource::<turn::Scheduler>(),
                 world.entities(),
                 world.read::<player::Player>(),
                 world.write::<ai::ChaseBehavior>(),
                 world.write::<ai::Dead>(),
-                world.write::<combat::Attack>(),
+                world.write::<combat::Attacked>(),
                 world.read::<combat::DamageReduction>(),
+                world.write::<combat::TemporaryDamageReduction>(),
+                world.read::<health::Cover>(),
                 world.write::<health::Health>(),
+                world.write::<noise::Noise>(),
                 world.read::<position::Position>(),
+                world.write::<status::StatusEffects>(),
             )
         });
 
+        let mut expired = vec![];
+        for (entity, temp) in (&entities, &temp_dr).iter() {
+            if scheduler.turns >= temp.expires {
+                expired.push(entity);
+            }
+        }
+        for entity in expired {
+            temp_dr.remove(entity);
+            if players.get(entity).is_some() {
+                self.events.publish(GameEvent::ShieldExpired);
+            }
+        }
+
         let mut to_delete = vec![];
         let mut to_kill = vec![];
-        for (entity, attack, health) in (&entities, &mut attacked, &mut healths).iter() {
-            let mut damage = rand::thread_rng().gen_range(attack.damage.0, attack.damage.1);
-
-            if let Some(dr) = dr.get(entity) {
-                let orig = damage;
-                damage = damage.saturating_sub(dr.value);
-                if dr.value > 0 {
-                    self.message_queue.send(format!("DR: {} -> {}", orig, damage)).unwrap();
+        let mut to_afflict = vec![];
+        for (entity, pending, health) in (&entities, &mut attacked, &mut healths).iter() {
+            for attack in pending.attacks.drain(..) {
+                let mut rolled = rngs.combat.gen_range(attack.damage.0, attack.damage.1);
+                let critical = rngs.combat.gen_range(0, 1000) < attack.crit_chance;
+                if critical {
+                    rolled = ::util::combat::crit(rolled, &attack);
+                }
+                let absorbed = ::std::cmp::min(rolled, ::util::combat::reduction(entity, &attack, &dr, &temp_dr));
+                let damage = rolled - absorbed;
+
+                if damage >= health.health {
+                    self.events.publish(GameEvent::Damaged {
+                        entity: entity,
+                        critical: critical,
+                        rolled: rolled,
+                        damage_type: attack.damage_type,
+                        absorbed: absorbed,
+                        left: 0,
+                    });
+                    to_kill.push(entity);
+                    // Whatever else landed hits a corpse
+                    break;
                 }
-            }
 
-            if damage >= health.health {
-                self.message_queue.send(format!("Hit for {} damage, killed!", damage)).unwrap();
-                to_kill.push(entity);
-            }
-            else {
                 // If not player, add chase behavior
                 if let None = players.get(entity) {
                     if let Some(pos) = positions.get(attack.source) {
-                        chasers.insert(entity, ai::ChaseBehavior {
-                            spotted: Some((pos.x, pos.y)),
-                        });
+                        if let Some(chaser) = chasers.get_mut(entity) {
+                            chaser.spot((pos.x, pos.y));
+                        }
+                        else {
+                            let mut chaser = ai::ChaseBehavior::new();
+                            chaser.spot((pos.x, pos.y));
+                            chasers.insert(entity, chaser);
+                        }
                     }
                 }
 
                 health.health -= damage;
-                self.message_queue.send(format!("Hit for {} damage, {} left", damage, health.health)).unwrap();
+                self.events.publish(GameEvent::Damaged {
+                    entity: entity,
+                    critical: critical,
+                    rolled: rolled,
+                    damage_type: attack.damage_type,
+                    absorbed: absorbed,
+                    left: health.health,
+                });
+
+                if let Some(effect) = attack.status {
+                    to_afflict.push((entity, effect));
+                }
             }
             to_delete.push(entity);
         }
@@ -84,8 +133,114 @@ impl specs::System<()> for CombatSystem {
             attacked.remove(entity);
         }
 
+        for (entity, effect) in to_afflict {
+            let who = if players.get(entity).is_some() { Who::Player } else { Who::Enemy };
+            self.events.publish(GameEvent::StatusApplied { who: who, kind: effect.kind });
+
+            if let Some(effects) = statuses.get_mut(entity) {
+                effects.add(effect);
+                continue;
+            }
+            let mut effects = status::StatusEffects::new();
+            effects.add(effect);
+            statuses.insert(entity, effects);
+        }
+
         for entity in to_kill {
+            // Cover coming apart is loud
+            if covers.get(entity).is_some() {
+                if let Some(pos) = positions.get(entity) {
+                    noises.insert(arg.create(), noise::Noise::new(*pos, noise::COVER_BREAK_NOISE, None));
+                }
+            }
             dead.insert(entity, ai::Dead);
         }
     }
 }
+
+impl ExplosionSystem {
+    pub fn new(events: EventBus) -> ExplosionSystem {
+        ExplosionSystem {
+            events: events,
+        }
+    }
+}
+
+impl specs::System<()> for ExplosionSystem {
+    fn run(&mut self, arg: specs::RunArg, _: ()) {
+        let (mut map, mut rngs, entities, mut explosions, mut attacked, mut dead, covers, healths, mut noises) = arg.fetch(|world| {
+            (
+                world.write_resource::<map::Map>(),
+                world.write_resource::<Rngs>(),
+                world.entities(),
+                world.write::<combat::Explosion>(),
+                world.write::<combat::Attacked>(),
+                world.write::<ai::Dead>(),
+                world.read::<health::Cover>(),
+                world.read::<health::Health>(),
+                world.write::<noise::Noise>(),
+            )
+        });
+
+        let mut to_delete = vec![];
+        for (entity, explosion) in (&entities, &mut explosions).iter() {
+            if explosion.detonated {
+                if explosion.ticks_left == 0 {
+                    to_delete.push(entity);
+                }
+                else {
+                    explosion.ticks_left -= 1;
+                }
+                continue;
+            }
+
+            // The blast doesn't go through walls, but it can break
+            // the ones it reaches
+            let mut cells = vec![];
+            ::util::fov::compute(&map, (explosion.center.x, explosion.center.y), explosion.radius, |x, y| {
+                cells.push((x, y));
+            });
+            cells.sort();
+            cells.dedup();
+
+            let mut breached = 0;
+            for &(x, y) in cells.iter() {
+                if map.occupable(x, y) {
+                    continue;
+                }
+                let damage = rngs.combat.gen_range(explosion.attack.damage.0, explosion.attack.damage.1);
+                if map.damage_terrain(x, y, damage) {
+                    breached += 1;
+                }
+            }
+            cells.retain(|&(x, y)| map.occupable(x, y));
+
+            let mut destroyed = 0;
+            for &(x, y) in cells.iter() {
+                if let Some(target) = map.contents(x, y) {
+                    if covers.get(target).is_some() {
+                        dead.insert(target, ai::Dead);
+                        destroyed += 1;
+                    }
+                    else if healths.get(target).is_some() {
+                        combat::Attacked::push(&mut attacked, target, explosion.attack);
+                    }
+                }
+            }
+
+            self.events.publish(GameEvent::Exploded {
+                center: explosion.center,
+                cover_destroyed: destroyed,
+                breached: breached > 0,
+            });
+            noises.insert(arg.create(), noise::Noise::new(explosion.center, noise::EXPLOSION_NOISE, None));
+
+            explosion.cells = cells.into_iter().map(|(x, y)| position::Position::new(x, y)).collect();
+            explosion.detonated = true;
+        }
+
+        for entity in to_delete {
+            arg.delete(entity);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use specs::{self, Join};
+
+use ::components::{map, position, ui};
+use ::components::map::Visibility;
+use ::util;
+
+const FOV_RADIUS: usize = 12;
+
+/// Recompute what the focused entity can see.
+pub struct FovSystem {
+}
+
+impl FovSystem {
+    pub fn new() -> FovSystem {
+        FovSystem {
+        }
+    }
+}
+
+impl specs::System<()> for FovSystem {
+    fn run(&mut self, arg: specs::RunArg, _: ()) {
+        let (mut map, focused, positions) = arg.fetch(|world| {
+            (
+                world.write_resource::<map::Map>(),
+                world.read::<ui::Focus>(),
+                world.read::<position::Position>(),
+            )
+        });
+
+        let origin = if let Some((_, position)) = (&focused, &positions).iter().next() {
+            (position.x, position.y)
+        }
+        else {
+            return;
+        };
+
+        for cell in map.visibility.iter_mut() {
+            if *cell == Visibility::Visible {
+                *cell = Visibility::Remembered;
+            }
+        }
+
+        let width = map.width;
+        let mut visible = Vec::new();
+        util::fov::compute(&map, origin, FOV_RADIUS, |x, y| visible.push(y * width + x));
+        for index in visible {
+            map.visibility[index] = Visibility::Visible;
+        }
+    }
+}
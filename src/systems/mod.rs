@@ -13,6 +13,9 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+pub mod action;
 pub mod ai;
 pub mod combat;
+pub mod fov;
+pub mod noise;
 pub mod ui;
@@ -0,0 +1,74 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use specs::{self, Join};
+
+use ::components::{ai, map, noise, player, position};
+
+/// Lets enemies hear noises made since the last run, then clears
+/// them away.
+pub struct NoiseSystem;
+
+impl NoiseSystem {
+    pub fn new() -> NoiseSystem {
+        NoiseSystem
+    }
+}
+
+/// The cost of sound reaching a cell: walls and closed doors muffle
+/// it, and nothing gets out of the map.
+fn sound_cost(map: &map::Map, x: usize, y: usize) -> Option<usize> {
+    match map.cell(x, y) {
+        map::MapCell::Null => None,
+        _ if map.opaque(x, y) => Some(1 + noise::WALL_ATTENUATION),
+        _ => Some(1),
+    }
+}
+
+impl specs::System<()> for NoiseSystem {
+    fn run(&mut self, arg: specs::RunArg, _: ()) {
+        let (map, entities, noises, mut chasers, mut perceptions, dead, players, positions) = arg.fetch(|world| {
+            (
+                world.read_resource::<map::Map>(),
+                world.entities(),
+                world.read::<noise::Noise>(),
+                world.write::<ai::ChaseBehavior>(),
+                world.write::<ai::Perception>(),
+                world.read::<ai::Dead>(),
+                world.read::<player::Player>(),
+                world.read::<position::Position>(),
+            )
+        });
+
+        for (entity, noise) in (&entities, &noises).iter() {
+            let center = (noise.center.x, noise.center.y);
+            let heard = ::util::path::flood(&map, center, noise.radius, |x, y| sound_cost(&map, x, y));
+
+            for (listener, chaser, pos, _) in (&entities, &mut chasers, &positions, !&dead).iter() {
+                if players.get(listener).is_some() || Some(listener) == noise.source || (pos.x, pos.y) == center {
+                    continue;
+                }
+                if heard.contains_key(&(pos.x, pos.y)) {
+                    chaser.spot(center);
+                    if let Some(perception) = perceptions.get_mut(listener) {
+                        perception.last_seen = Some(center);
+                    }
+                }
+            }
+
+            arg.delete(entity);
+        }
+    }
+}
@@ -13,6 +13,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::fmt;
 use std::sync::mpsc;
 
 use specs::{self, Join};
@@ -20,6 +21,8 @@ use voodoo::color::ColorValue;
 use voodoo::window::{FormattedString, Point, Window};
 
 use ::components;
+use ::events::{AttackOutcome, EventBus, GameEvent, Who};
+use ::keymap::{Action, Keymap};
 
 macro_rules! make_resource {
     ($name: ident) => {
@@ -42,9 +45,43 @@ pub struct InfoPanelSystem {
 
 make_resource!(InfoPanelResource);
 make_resource!(CommandPanelResource);
-make_resource!(MessagesPanelResource);
 make_resource!(InventoryPanelResource);
 
+/// The message panel beside the map.
+pub struct MessagesPanelResource {
+    pub window: Window,
+    /// How many lines back from the newest the panel is scrolled.
+    pub scroll: usize,
+}
+
+/// The full-screen message history.
+pub struct HistoryPanelResource {
+    pub window: Window,
+    /// How many lines back from the newest the history is scrolled.
+    pub scroll: usize,
+    /// Only show messages of this kind, if set.
+    pub filter: Option<MessageKind>,
+}
+
+impl MessagesPanelResource {
+    pub fn new(window: Window) -> MessagesPanelResource {
+        MessagesPanelResource {
+            window: window,
+            scroll: 0,
+        }
+    }
+}
+
+impl HistoryPanelResource {
+    pub fn new(window: Window) -> HistoryPanelResource {
+        HistoryPanelResource {
+            window: window,
+            scroll: 0,
+            filter: None,
+        }
+    }
+}
+
 impl InfoPanelSystem {
     pub fn new() -> InfoPanelSystem {
         InfoPanelSystem {
@@ -54,62 +91,344 @@ impl InfoPanelSystem {
 
 impl specs::System<()> for InfoPanelSystem {
     fn run(&mut self, arg: specs::RunArg, _: ()) {
-        let (mut res, focus, health) = arg.fetch(|world| {
-            (world.write_resource::<InfoPanelResource>(), world.read::<components::ui::Focus>(), world.read::<components::health::Health>())
+        let (mut res, dungeon, rngs, entities, focus, crouching, sneaking, health, statuses) = arg.fetch(|world| {
+            (
+                world.write_resource::<InfoPanelResource>(),
+                world.read_resource::<components::map::Dungeon>(),
+                world.read_resource::<::util::rng::Rngs>(),
+                world.entities(),
+                world.read::<components::ui::Focus>(),
+                world.read::<components::combat::Crouching>(),
+                world.read::<components::noise::Sneaking>(),
+                world.read::<components::health::Health>(),
+                world.read::<components::status::StatusEffects>(),
+            )
         });
 
-        for (_, health) in (&focus, &health).iter() {
+        for (entity, _, health) in (&entities, &focus, &health).iter() {
             res.window.print_at(Point::new(1, 0), "Health ");
-            let h = format!("{:30}", format!("{}/{}", health.health, health.max_health));
+            let h = format!("{:14}", format!("{}/{}", health.health, health.max_health));
             let mut hfs: FormattedString = (&h).into();
             hfs.bg = Some(ColorValue::Red);
             res.window.print_at(Point::new(9, 0), hfs);
+
+            // Abbreviated, to fit beside the health bar
+            let mut effects: Vec<String> = statuses.get(entity).map_or(vec![], |s| s.effects.iter().map(|e| {
+                if e.stacks > 1 {
+                    format!("{}{}", e.kind.abbreviation(), e.stacks)
+                }
+                else {
+                    e.kind.abbreviation().to_owned()
+                }
+            }).collect());
+            if sneaking.get(entity).is_some() {
+                effects.insert(0, "SNK".into());
+            }
+            if crouching.get(entity).is_some() {
+                effects.insert(0, "CRH".into());
+            }
+            res.window.print_at(Point::new(24, 0), &format!("{:16}", effects.join(" ")));
+        }
+
+        res.window.print_at(Point::new(1, 1), &format!("Depth {:<3} Seed {}", dungeon.depth, rngs.seed));
+    }
+}
+
+/// What a message is about, which decides its color.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum MessageKind {
+    Combat,
+    Loot,
+    System,
+}
+
+/// The kinds of message the history can be filtered to.
+pub const MESSAGE_KINDS: [MessageKind; 3] = [MessageKind::Combat, MessageKind::Loot, MessageKind::System];
+
+/// A message in the log, and how many times in a row it was
+/// repeated.
+#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct Message {
+    pub text: String,
+    pub kind: MessageKind,
+    pub count: usize,
+}
+
+/// Every message shown so far, kept so that it can be saved.
+pub struct MessageLog {
+    pub messages: Vec<Message>,
+}
+
+impl MessageKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MessageKind::Combat => "combat",
+            MessageKind::Loot => "loot",
+            MessageKind::System => "system",
+        }
+    }
+
+    pub fn color(&self) -> ColorValue {
+        match *self {
+            MessageKind::Combat => ColorValue::Red,
+            MessageKind::Loot => ColorValue::Yellow,
+            MessageKind::System => ColorValue::Cyan,
+        }
+    }
+
+    /// The next kind to filter the history to, going back to no
+    /// filter after the last one.
+    pub fn next(filter: Option<MessageKind>) -> Option<MessageKind> {
+        match filter {
+            None => Some(MESSAGE_KINDS[0]),
+            Some(kind) => {
+                let index = MESSAGE_KINDS.iter().position(|&k| k == kind).unwrap();
+                MESSAGE_KINDS.get(index + 1).cloned()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.count > 1 {
+            write!(f, "{} x{}", self.text, self.count)
+        }
+        else {
+            write!(f, "{}", self.text)
+        }
+    }
+}
+
+impl MessageLog {
+    pub fn new() -> MessageLog {
+        MessageLog {
+            messages: Vec::new(),
+        }
+    }
+
+    /// Add a message, or count it again if it's the same as the last
+    /// one.
+    pub fn push(&mut self, text: String, kind: MessageKind) {
+        if let Some(last) = self.messages.last_mut() {
+            if last.text == text && last.kind == kind {
+                last.count += 1;
+                return;
+            }
+        }
+        self.messages.push(Message {
+            text: text,
+            kind: kind,
+            count: 1,
+        });
+    }
+
+    /// The messages of a kind, or all of them, wrapped to `width`,
+    /// oldest first.
+    pub fn lines(&self, width: usize, filter: Option<MessageKind>) -> Vec<(String, MessageKind)> {
+        let mut lines = Vec::new();
+        for message in self.messages.iter() {
+            if filter.map_or(false, |kind| kind != message.kind) {
+                continue;
+            }
+            for line in ::ui::wrap(&message.to_string(), width) {
+                lines.push((line, message.kind));
+            }
         }
+        lines
     }
 }
 
+/// Writes what happens to the message log, and shows it.
 pub struct MessagesPanelSystem {
-    pub messages: Vec<String>,
-    pub incoming: mpsc::Receiver<String>,
-    pub cursor: Point,
+    pub incoming: mpsc::Receiver<GameEvent>,
+    /// The scrolling of the panel and history, and the history's
+    /// filter, as last painted.
+    painted: Option<(usize, usize, Option<MessageKind>)>,
 }
 
 impl MessagesPanelSystem {
-    pub fn new() -> (MessagesPanelSystem, mpsc::Sender<String>) {
-        let (tx, rx) = mpsc::channel();
-        (MessagesPanelSystem {
-            messages: Vec::new(),
-            incoming: rx,
-            cursor: Point::new(0, 0),
-        }, tx)
+    pub fn new(events: &EventBus) -> MessagesPanelSystem {
+        MessagesPanelSystem {
+            incoming: events.subscribe(),
+            painted: None,
+        }
     }
 }
 
-impl specs::System<()> for MessagesPanelSystem {
-    fn run(&mut self, arg: specs::RunArg, _: ()) {
-        let mut res = arg.fetch(|world| {
-            world.write_resource::<MessagesPanelResource>()
-        });
+/// Show as many lines as fit, ending `scroll` lines back from the
+/// newest, and say how many more there are below. Returns the scroll,
+/// limited to how far back there is to go.
+fn paint(window: &mut Window, lines: &[(String, MessageKind)], scroll: usize) -> usize {
+    window.clear();
+    let height = window.height as usize;
+    let scroll = if lines.len() > height {
+        ::std::cmp::min(scroll, lines.len() + 1 - height)
+    }
+    else {
+        0
+    };
+    // Keep the bottom line free to say there's more
+    let rows = if scroll > 0 { height - 1 } else { height };
+    let end = lines.len() - scroll;
+    let start = end.saturating_sub(rows);
+
+    for (y, &(ref line, kind)) in lines[start..end].iter().enumerate() {
+        let mut formatted: FormattedString = line.into();
+        formatted.fg = Some(kind.color());
+        window.print_at(Point::new(0, y as u16), formatted);
+    }
+    if scroll > 0 {
+        window.print_at(Point::new(0, rows as u16), &format!("↓ {} more", scroll));
+    }
+    scroll
+}
+
+/// What kind of message an event makes.
+pub fn event_kind(event: &GameEvent) -> MessageKind {
+    use ::events::GameEvent::*;
+
+    match *event {
+        AttackResolved { .. } | Damaged { .. } | EntityKilled { .. } | StatusDamage { .. } |
+        StatusApplied { .. } | Stunned { .. } | ShieldExpired | Thrown { .. } | Exploded { .. } |
+        Reloaded { .. } | ReloadFailed(_) | Spotted | CalledForHelp => MessageKind::Combat,
 
-        let mut need_repaint = false;
-        for message in self.incoming.try_iter() {
-            if self.cursor.y < res.window.height - 1 {
-                res.window.print_at(self.cursor, &message);
-                self.cursor.x = 0;
-                self.cursor.y += 1;
+        ItemPicked { .. } | ItemEquipped { .. } | CannotEquip { .. } | Healed { .. } |
+        Shielded { .. } => MessageKind::Loot,
+
+        Crouched(_) | Sneaking(_) | DoorOpened { .. } | DoorKicked { .. } | MapGenerating |
+        MapGenerated | Descended { .. } | Notice(_) => MessageKind::System,
+    }
+}
+
+/// The lines in the message log for an event, if it gets any.
+pub fn describe_event(event: &GameEvent, keymap: &Keymap) -> Vec<String> {
+    use ::events::GameEvent::*;
+
+    let message: String = match *event {
+        AttackResolved { who, outcome } => match (who, outcome) {
+            (Who::Player, AttackOutcome::NothingEquipped) => "You have nothing equipped!".into(),
+            (Who::Player, AttackOutcome::Miss) => "You missed!".into(),
+            (Who::Player, AttackOutcome::HitNothing) => "You hit nothing.".into(),
+            (Who::Player, AttackOutcome::HitTerrain { name, breached: true }) => format!("You blast through the {}!", name),
+            (Who::Player, AttackOutcome::HitTerrain { name, breached: false }) => format!("You hit the {}.", name),
+            (Who::Player, AttackOutcome::HitEntity(pos)) |
+            (Who::Player, AttackOutcome::Explosive(pos)) => format!("Targeted {}, {}", pos.x, pos.y),
+            (Who::Player, AttackOutcome::OutOfRange) => "You can't hit that with a melee weapon.".into(),
+            (Who::Player, AttackOutcome::OutOfAmmo) => format!(
+                "Click! Your weapon is empty. Press {} to reload.", keymap.label(Action::Reload)),
+            (Who::Player, AttackOutcome::NoTarget) => "You can't target yourself.".into(),
+            (_, AttackOutcome::NothingEquipped) |
+            (_, AttackOutcome::NoTarget) => return vec![],
+            (_, AttackOutcome::Miss) => "Enemy missed!".into(),
+            (_, AttackOutcome::HitNothing) => "Enemy hit nothing.".into(),
+            (_, AttackOutcome::HitTerrain { name, breached: true }) => format!("Enemy blasts through the {}!", name),
+            (_, AttackOutcome::HitTerrain { name, breached: false }) => format!("Enemy hit the {}.", name),
+            (_, AttackOutcome::HitEntity(pos)) => format!("Enemy targeted {}, {}", pos.x, pos.y),
+            (_, AttackOutcome::OutOfRange) => "Enemy tried a melee weapon out of range.".into(),
+            (_, AttackOutcome::OutOfAmmo) => "Enemy's weapon clicks empty.".into(),
+            (_, AttackOutcome::Explosive(pos)) => format!("Enemy fires at {}, {}", pos.x, pos.y),
+        },
+        Damaged { critical, rolled, damage_type, absorbed, left, .. } => {
+            // e.g. "Crit! 9 ballistic, 2 absorbed"
+            let mut breakdown = format!("{}{} {}", if critical { "Crit! " } else { "" }, rolled, damage_type.name());
+            if absorbed > 0 {
+                breakdown.push_str(&format!(", {} absorbed", absorbed));
+            }
+            if left == 0 {
+                format!("{}. Killed!", breakdown)
             }
             else {
-                need_repaint = true;
+                format!("{}. {} left.", breakdown, left)
             }
-            self.messages.push(message);
         }
+        // Already reported along with the damage that did it
+        EntityKilled { .. } => return vec![],
+        StatusDamage { who: Who::Player, damage, left } => format!("You suffer {} damage, {} left.", damage, left),
+        StatusDamage { damage, .. } => format!("Enemy suffers {} damage.", damage),
+        StatusApplied { who: Who::Player, kind } => format!("You are {}!", kind.name()),
+        StatusApplied { kind, .. } => format!("Target is {}!", kind.name()),
+        Stunned { who: Who::Player } => "You are stunned and lose your turn.".into(),
+        Stunned { .. } => "Enemy is stunned.".into(),
+        ShieldExpired => "Your shield wears off.".into(),
+        Thrown { ref item } => format!("You throw the {}.", item),
+        Exploded { cover_destroyed, breached, .. } => {
+            let mut messages = vec!["Boom!".to_owned()];
+            if cover_destroyed > 0 {
+                messages.push(format!("The blast destroys {} cover.", cover_destroyed));
+            }
+            if breached {
+                messages.push("The blast breaks through the walls!".into());
+            }
+            return messages;
+        }
+
+        ItemPicked { who: Who::Player, ref item } => format!("Picked up {}", item),
+        ItemPicked { ref item, .. } => format!("Enemy picks up {}.", item),
+        ItemEquipped { damage_reduction, .. } if damage_reduction > 0 => format!("DR: {}", damage_reduction),
+        ItemEquipped { .. } => return vec![],
+        CannotEquip { ref item } => format!("You can't equip {}.", item),
+        Healed { amount } => format!("You heal {} health.", amount),
+        Shielded { damage_reduction, turns } => format!("DR +{} for {} turns.", damage_reduction, turns),
+        Reloaded { who: Who::Player, loaded } => format!("Reloaded. {} rounds in the magazine.", loaded),
+        Reloaded { .. } => "Enemy reloads.".into(),
+        ReloadFailed(components::player::Reload::Full) => "Your weapon is already fully loaded.".into(),
+        ReloadFailed(components::player::Reload::NoAmmo) => "You have no ammo for your weapon.".into(),
+        ReloadFailed(_) => "Your weapon doesn't take ammo.".into(),
+        Crouched(true) => "You crouch down.".into(),
+        Crouched(false) => "You stand up.".into(),
+        Sneaking(true) => "You start sneaking.".into(),
+        Sneaking(false) => "You stop sneaking.".into(),
+
+        DoorOpened { who: Who::Player } => "You open the door.".into(),
+        DoorOpened { .. } => "A door opens.".into(),
+        DoorKicked { broken: true, .. } => "The door gives way!".into(),
+        DoorKicked { who: Who::Player, broken: false } => "You kick the locked door.".into(),
+        DoorKicked { .. } => "Something kicks at a locked door.".into(),
 
-        if need_repaint {
-            res.window.clear();
-            let base = self.messages.len() - res.window.height as usize;
-            for y in 0..res.window.height {
-                res.window.print_at(Point::new(0, y), &self.messages[base + y as usize]);
+        Spotted => "Enemy spots you!".into(),
+        CalledForHelp => "Enemy calls for help!".into(),
+
+        MapGenerating => "Generating map…".into(),
+        MapGenerated => "Placing player and enemies…".into(),
+        Descended { depth } => format!("You descend to depth {}.", depth),
+
+        Notice(ref message) => message.clone(),
+    };
+    vec![message]
+}
+
+impl specs::System<()> for MessagesPanelSystem {
+    fn run(&mut self, arg: specs::RunArg, _: ()) {
+        let (mut res, mut history, mut log, keymap) = arg.fetch(|world| {
+            (
+                world.write_resource::<MessagesPanelResource>(),
+                world.write_resource::<HistoryPanelResource>(),
+                world.write_resource::<MessageLog>(),
+                world.read_resource::<Keymap>(),
+            )
+        });
+
+        let mut arrived = false;
+        for event in self.incoming.try_iter() {
+            let kind = event_kind(&event);
+            for message in describe_event(&event, &keymap) {
+                log.push(message, kind);
+                arrived = true;
             }
         }
+        // Anything new brings the panel back to the latest messages
+        if arrived {
+            res.scroll = 0;
+        }
+        else if self.painted == Some((res.scroll, history.scroll, history.filter)) {
+            return;
+        }
+
+        let (scroll, lines) = (res.scroll, log.lines(res.window.width as usize, None));
+        res.scroll = paint(&mut res.window, &lines, scroll);
+        let (scroll, lines) = (history.scroll, log.lines(history.window.width as usize, history.filter));
+        history.scroll = paint(&mut history.window, &lines, scroll);
+        self.painted = Some((res.scroll, history.scroll, history.filter));
     }
 }
@@ -19,11 +19,90 @@ pub mod list;
 
 pub use self::list::{List, ListRenderable};
 
+/// A serializable stand-in for voodoo's `ColorValue`.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
 pub struct ColorPair {
     pub fg: ColorValue,
     pub bg: ColorValue,
 }
 
+/// Break text into lines no wider than `width`, between words where
+/// possible.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = ::std::cmp::max(width, 1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+
+    for word in text.split_whitespace() {
+        let length = word.chars().count();
+        if line_width > 0 && line_width + 1 + length > width {
+            lines.push(::std::mem::replace(&mut line, String::new()));
+            line_width = 0;
+        }
+        if line_width > 0 {
+            line.push(' ');
+            line_width += 1;
+        }
+        for c in word.chars() {
+            // Words too long for a line of their own get split up
+            if line_width == width {
+                lines.push(::std::mem::replace(&mut line, String::new()));
+                line_width = 0;
+            }
+            line.push(c);
+            line_width += 1;
+        }
+    }
+
+    if line_width > 0 || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+impl Color {
+    pub fn from_name(name: &str) -> Option<Color> {
+        match name {
+            "black" => Some(Color::Black),
+            "red" => Some(Color::Red),
+            "green" => Some(Color::Green),
+            "yellow" => Some(Color::Yellow),
+            "blue" => Some(Color::Blue),
+            "magenta" => Some(Color::Magenta),
+            "cyan" => Some(Color::Cyan),
+            "white" => Some(Color::White),
+            _ => None,
+        }
+    }
+}
+
+impl Into<ColorValue> for Color {
+    fn into(self) -> ColorValue {
+        match self {
+            Color::Black => ColorValue::Black,
+            Color::Red => ColorValue::Red,
+            Color::Green => ColorValue::Green,
+            Color::Yellow => ColorValue::Yellow,
+            Color::Blue => ColorValue::Blue,
+            Color::Magenta => ColorValue::Magenta,
+            Color::Cyan => ColorValue::Cyan,
+            Color::White => ColorValue::White,
+        }
+    }
+}
+
 impl ColorPair {
     pub fn new(fg: ColorValue, bg: ColorValue) -> ColorPair {
         ColorPair {
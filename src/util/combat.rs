@@ -13,16 +13,65 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use rand::{self, Rng};
+use rand::Rng;
 use specs::Entity;
 
 use super::HasStorage;
 
-use ::components::combat::Attack;
+use ::components::combat::{Attack, Crouching, DamageReduction, Explosion, TemporaryDamageReduction};
 use ::components::health::{Cover, Health};
-use ::components::map::Map;
+use ::components::map::{Map, MapCell};
 use ::components::player::{Equip, Item, ItemKind};
 use ::components::position::Position;
+use ::components::status::StatusEffects;
+
+/// Accuracy lost shooting at something with a wall for full cover.
+pub const WALL_COVER_PENALTY: i32 = -250;
+/// Accuracy lost shooting beyond a weapon's range.
+pub const RANGE_PENALTY: i32 = -100;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum CoverLevel {
+    None,
+    /// Cover beside the line of fire: half its penalty applies.
+    Half,
+    /// Cover squarely between the target and the shooter.
+    Full,
+}
+
+/// The chance of a shot hitting, and what went into it.
+#[derive(Clone,Debug)]
+pub struct HitChance {
+    /// Out of 1000.
+    pub chance: i32,
+    pub modifiers: Vec<(&'static str, i32)>,
+    /// The cover the target has, after crouching.
+    pub cover: CoverLevel,
+}
+
+/// What a shot would likely do, worked out without taking it.
+#[derive(Clone,Debug)]
+pub struct Prediction {
+    /// The chance of hitting the target, out of 1000, allowing for
+    /// anything in the way.
+    pub chance: i32,
+    /// What went into the chance of hitting the target, were there
+    /// nothing in the way.
+    pub hit: HitChance,
+    /// The chance of something in the way taking the shot instead.
+    pub intercepted: i32,
+    /// Why the shot can't hit the target at all, if it can't.
+    pub blocked: Option<&'static str>,
+    /// Damage on a hit, inclusive, after the target's damage
+    /// reduction.
+    pub damage: (usize, usize),
+    /// Damage on a critical hit, likewise.
+    pub crit_damage: (usize, usize),
+    /// The attack the shot would make.
+    pub attack: Attack,
+    /// The blast radius, for explosive weapons.
+    pub radius: usize,
+}
 
 pub enum CombatResult {
     /// No weapon
@@ -31,81 +80,478 @@ pub enum CombatResult {
     Miss,
     /// You targeted nothing
     HitNothing,
-    /// You hit a wall
-    HitEnvironment,
+    /// You hit a wall (or a closed door)
+    HitEnvironment(Position, Attack),
     /// You hit
     HitEntity(Entity, Position, Attack),
     /// You're using a melee weapon
     OutOfRange,
+    /// Your weapon's magazine is empty
+    OutOfAmmo,
+    /// You aimed at your own cell
+    NoTarget,
+    /// Your shot exploded
+    Explode(Explosion),
 }
 
-pub fn resolve<H, C>(map: &Map, attacker: Entity, equip: &Equip,
-                     origin: Position, target: Position,
-                     is_melee: bool,
-                     targetable: &H,
-                     cover: &C) -> CombatResult
-    where H: HasStorage<Health>, C: HasStorage<Cover> {
+/// Resolve an attack along the line from `origin` to `target`,
+/// spending a round if the weapon takes ammo and the shot is taken:
+/// nothing is spent on an empty weapon, a melee attack out of reach,
+/// or aiming at the attacker's own cell.
+/// Explosive shots go off wherever they stop.
+pub fn resolve<H, C, S, K, R>(map: &Map, attacker: Entity, equip: &mut Equip,
+                           origin: Position, target: Position,
+                           is_melee: bool,
+                           targetable: &H,
+                           cover: &C,
+                           statuses: &S,
+                           crouching: &K,
+                           rng: &mut R) -> CombatResult
+    where H: HasStorage<Health>, C: HasStorage<Cover>, S: HasStorage<StatusEffects>,
+          K: HasStorage<Crouching>, R: Rng {
     let points = ::util::bresenham(origin, target);
 
-    let attack = if let &Some(Item {
-        kind: ItemKind::Weapon {
-            damage, accuracy, range,
-        },
-        ..
-    }) = if is_melee { &equip.secondary } else { &equip.primary } {
-        Some((Attack {
+    let weapon = if is_melee { &mut equip.secondary } else { &mut equip.primary };
+    let (attack, range, radius) = match weapon_attack(attacker, weapon) {
+        Some(attack) => attack,
+        None => return CombatResult::NothingEquipped,
+    };
+    if let Some(Item { kind: ItemKind::Weapon { magazine: Some(magazine), .. }, .. }) = *weapon {
+        if magazine.loaded == 0 {
+            return CombatResult::OutOfAmmo;
+        }
+    }
+    if origin == target {
+        return CombatResult::NoTarget;
+    }
+
+    let result = {
+        let outcome = |result: CombatResult, center: Position| if radius > 0 {
+            CombatResult::Explode(Explosion::new(center, radius, attack))
+        }
+        else {
+            result
+        };
+
+        let mut shoot = || {
+            let last = points.len() - 1;
+            for (index, target) in points.iter().enumerate() {
+                if *target == origin {
+                    continue;
+                }
+                if let Some(entity) = map.contents(target.x, target.y) {
+                    // Melee weapons reach all eight neighbours
+                    let adjacent = ::util::chebyshev((origin.x, origin.y), (target.x, target.y)) <= 1;
+                    if range == 0 && !adjacent {
+                        return CombatResult::OutOfRange;
+                    }
+
+                    let chance = chance_along(map, attacker, attack.accuracy, range, origin, *target,
+                                              index == last, cover, statuses, crouching);
+                    if targetable.check(entity) && rng.gen_range(0, 1000) < chance {
+                        return outcome(CombatResult::HitEntity(entity, *target, attack), *target);
+                    }
+                    else if index == last || range == 0 && adjacent {
+                        return outcome(CombatResult::Miss, *target);
+                    }
+                }
+                if !map.occupable(target.x, target.y) {
+                    return outcome(CombatResult::HitEnvironment(*target, attack), points[index - 1]);
+                }
+            }
+
+            outcome(CombatResult::HitNothing, points[last])
+        };
+        shoot()
+    };
+
+    // Only a shot that was actually taken uses up a round
+    if let CombatResult::OutOfRange = result {
+        return result;
+    }
+    if let Some(Item { kind: ItemKind::Weapon { magazine: Some(ref mut magazine), .. }, .. }) = *weapon {
+        magazine.loaded -= 1;
+    }
+    result
+}
+
+/// Work out the odds of a shot from `origin` at whatever is at
+/// `target`, and how much damage it would do, the same way `resolve`
+/// would take it. Returns `None` if there's no weapon to shoot with
+/// or nothing to shoot at.
+pub fn predict<H, C, S, K, D, T>(map: &Map, attacker: Entity, equip: &Equip,
+                                 origin: Position, target: Position,
+                                 is_melee: bool,
+                                 targetable: &H,
+                                 cover: &C,
+                                 statuses: &S,
+                                 crouching: &K,
+                                 reductions: &D,
+                                 temporary_reductions: &T) -> Option<Prediction>
+    where H: HasStorage<Health>, C: HasStorage<Cover>, S: HasStorage<StatusEffects>,
+          K: HasStorage<Crouching>, D: HasStorage<DamageReduction>,
+          T: HasStorage<TemporaryDamageReduction> {
+    let weapon = if is_melee { &equip.secondary } else { &equip.primary };
+    let (attack, range, radius) = match weapon_attack(attacker, weapon) {
+        Some(attack) => attack,
+        None => return None,
+    };
+    let loaded = match *weapon {
+        Some(Item { kind: ItemKind::Weapon { magazine: Some(magazine), .. }, .. }) => magazine.loaded > 0,
+        _ => true,
+    };
+    let defender = match map.contents(target.x, target.y) {
+        Some(entity) if target != origin => entity,
+        _ => return None,
+    };
+
+    let absorbed = reduction(defender, &attack, reductions, temporary_reductions);
+    let mut prediction = Prediction {
+        chance: 0,
+        hit: hit_chance(map, attacker, attack.accuracy, range, origin, target, cover, statuses, crouching),
+        intercepted: 0,
+        blocked: None,
+        damage: (attack.damage.0.saturating_sub(absorbed), (attack.damage.1 - 1).saturating_sub(absorbed)),
+        crit_damage: (crit(attack.damage.0, &attack).saturating_sub(absorbed),
+                      crit(attack.damage.1 - 1, &attack).saturating_sub(absorbed)),
+        attack: attack,
+        radius: radius,
+    };
+
+    if !loaded {
+        prediction.blocked = Some("Weapon empty");
+        return Some(prediction);
+    }
+    if range == 0 && ::util::chebyshev((origin.x, origin.y), (target.x, target.y)) > 1 {
+        prediction.blocked = Some("Out of reach");
+        return Some(prediction);
+    }
+
+    // Follow the shot, keeping track of how likely it is to get
+    // this far
+    let points = ::util::bresenham(origin, target);
+    let last = points.len() - 1;
+    let mut through = 1000;
+    for (index, point) in points.iter().enumerate() {
+        if *point == origin {
+            continue;
+        }
+        if index == last {
+            if targetable.check(defender) {
+                prediction.chance = through * prediction.hit.chance / 1000;
+            }
+            else {
+                prediction.blocked = Some("Can't be hurt");
+            }
+            break;
+        }
+        if let Some(entity) = map.contents(point.x, point.y) {
+            if targetable.check(entity) {
+                let chance = chance_along(map, attacker, attack.accuracy, range, origin, *point,
+                                          false, cover, statuses, crouching);
+                through = through * (1000 - ::std::cmp::min(1000, chance)) / 1000;
+            }
+        }
+        if !map.occupable(point.x, point.y) {
+            prediction.blocked = Some("Line of fire blocked");
+            break;
+        }
+    }
+    prediction.intercepted = 1000 - through;
+
+    Some(prediction)
+}
+
+/// The attack a weapon makes, with its range and blast radius.
+fn weapon_attack(attacker: Entity, weapon: &Option<Item>) -> Option<(Attack, usize, usize)> {
+    match *weapon {
+        Some(Item { kind: ItemKind::Weapon {
+            damage, damage_type, accuracy, penetration, crit_chance, crit_multiplier,
+            range, radius, status, breach, ..
+        }, .. }) => Some((Attack {
             damage: damage,
+            damage_type: damage_type,
             accuracy: accuracy,
+            penetration: penetration,
+            crit_chance: crit_chance,
+            crit_multiplier: crit_multiplier,
             source: attacker,
-        }, range))
+            status: status,
+            breach: breach,
+        }, range, radius)),
+        _ => None,
     }
-    else {
-        None
+}
+
+/// How much of an attack's damage `target`'s armor (and shields)
+/// take off, after penetration.
+pub fn reduction<D, T>(target: Entity, attack: &Attack, reductions: &D, temporary_reductions: &T) -> usize
+    where D: HasStorage<DamageReduction>, T: HasStorage<TemporaryDamageReduction> {
+    let total = reductions.get(target).map_or(0, |dr| dr.against(attack.damage_type)) +
+        temporary_reductions.get(target).map_or(0, |temp| temp.value);
+    total.saturating_sub(attack.penetration)
+}
+
+/// A damage roll, multiplied as a critical hit.
+pub fn crit(damage: usize, attack: &Attack) -> usize {
+    damage * attack.crit_multiplier / 100
+}
+
+/// The chance of a shot aimed at `target` hitting whatever is at
+/// `point` along the way, out of 1000.
+fn chance_along<C, S, K>(map: &Map, attacker: Entity, accuracy: usize, range: usize,
+                         origin: Position, point: Position, is_target: bool,
+                         cover: &C, statuses: &S, crouching: &K) -> i32
+    where C: HasStorage<Cover>, S: HasStorage<StatusEffects>, K: HasStorage<Crouching> {
+    let chance = hit_chance(map, attacker, accuracy, range, origin, point, cover, statuses, crouching).chance;
+    // If there is cover in the way, and they are not targeting it,
+    // it's hard to hit - but not impossible
+    if !is_target {
+        if let Some(&Cover { penalty }) = cover.get(map.contents(point.x, point.y).unwrap()) {
+            return ::std::cmp::max(0, chance + penalty);
+        }
+    }
+    chance
+}
+
+/// The chance of hitting whatever is at `target` with a shot from
+/// `origin`, out of 1000.
+pub fn hit_chance<C, S, K>(map: &Map, attacker: Entity, accuracy: usize, range: usize,
+                           origin: Position, target: Position,
+                           cover: &C, statuses: &S, crouching: &K) -> HitChance
+    where C: HasStorage<Cover>, S: HasStorage<StatusEffects>, K: HasStorage<Crouching> {
+    let mut modifiers = vec![("Accuracy", accuracy as i32)];
+
+    // Suppressed attackers have a harder time hitting anything
+    let suppressed = statuses.get(attacker).map_or(0, |s| s.accuracy_penalty());
+    if suppressed > 0 {
+        modifiers.push(("Suppressed", -suppressed));
+    }
+
+    // Melee weapons have their own reach check
+    if range > 0 && ::util::distance2((origin.x, origin.y), (target.x, target.y)) > range * range {
+        modifiers.push(("Too far", RANGE_PENALTY));
+    }
+
+    let (mut level, penalty) = match map.contents(target.x, target.y) {
+        Some(_) => cover_against(map, cover, origin, target),
+        None => (CoverLevel::None, 0),
     };
+    // Crouching behind half cover makes the most of it
+    if let Some(defender) = map.contents(target.x, target.y) {
+        if level == CoverLevel::Half && crouching.get(defender).is_some() {
+            level = CoverLevel::Full;
+        }
+    }
+    match level {
+        CoverLevel::None => {}
+        CoverLevel::Half => modifiers.push(("Half cover", penalty / 2)),
+        CoverLevel::Full => modifiers.push(("Full cover", penalty)),
+    }
 
-    if let Some((attack, range)) = attack {
-        let mut accuracy_penalty = 0;
+    let chance = modifiers.iter().map(|&(_, value)| value).sum::<i32>();
+    HitChance {
+        chance: ::std::cmp::max(0, ::std::cmp::min(1000, chance)),
+        modifiers: modifiers,
+        cover: level,
+    }
+}
 
-        let last = points.len() - 1;
-        for (index, target) in points.iter().enumerate() {
-            if *target == origin {
-                continue;
-            }
-            if let Some(entity) = map.contents(target.x, target.y) {
-                // If there is cover, and they are not targeting it,
-                // influence the accuracy
-                if index != last {
-                    if let Some(&Cover { penalty }) = cover.get(entity) {
-                        accuracy_penalty += penalty;
-                        // Don't continue; here - you have a chance to hit
-                        // the cover
-                    }
-                }
+/// The best cover `target` has against a shot from `origin`, and its
+/// full penalty. Cover entities and walls in the cells around the
+/// target on the shooter's side count: the cell the shot comes in
+/// through gives full cover, the others half. Nothing gives cover
+/// against an adjacent shooter.
+pub fn cover_against<C>(map: &Map, cover: &C, origin: Position, target: Position) -> (CoverLevel, i32)
+    where C: HasStorage<Cover> {
+    if ::util::chebyshev((origin.x, origin.y), (target.x, target.y)) <= 1 {
+        return (CoverLevel::None, 0);
+    }
 
-                let dist = ::util::distance2((origin.x, origin.y), (target.x, target.y));
-                if range == 0 && dist > 1 {
-                    return CombatResult::OutOfRange;
-                }
+    let points = ::util::bresenham(origin, target);
+    let entry = points[points.len() - 2];
+    let towards = (origin.x as i32 - target.x as i32, origin.y as i32 - target.y as i32);
 
-                if dist > range * range {
-                    accuracy_penalty -= 100;
-                }
+    let mut best = (CoverLevel::None, 0);
+    for direction in ::util::DIRECTIONS.iter() {
+        let (dx, dy) = direction.offset();
+        // Only the side facing the shooter
+        if dx * towards.0 + dy * towards.1 <= 0 {
+            continue;
+        }
+        let x = target.x as i32 + dx;
+        let y = target.y as i32 + dy;
+        if x < 0 || y < 0 || x as usize >= map.width || y as usize >= map.height {
+            continue;
+        }
+        let (x, y) = (x as usize, y as usize);
 
-                if targetable.check(entity) && rand::thread_rng().gen_range(0, 1000) < attack.accuracy as i32 + accuracy_penalty {
-                    return CombatResult::HitEntity(entity, *target, attack);
-                }
-                else if index == last || range == 0 && dist <= 1 {
-                    return CombatResult::Miss;
+        let penalty = match map.contents(x, y).and_then(|entity| cover.get(entity)) {
+            Some(&Cover { penalty }) => penalty,
+            None if !map.occupable(x, y) && map.cell(x, y) != MapCell::Null => WALL_COVER_PENALTY,
+            None => continue,
+        };
+        let level = if (x, y) == (entry.x, entry.y) {
+            CoverLevel::Full
+        }
+        else {
+            CoverLevel::Half
+        };
+        let effective = if level == CoverLevel::Full { penalty } else { penalty / 2 };
+        let current = if best.0 == CoverLevel::Full { best.1 } else { best.1 / 2 };
+        if effective < current {
+            best = (level, penalty);
+        }
+    }
+    best
+}
+
+/// Damage the terrain an attack hit, if it's one that can breach.
+/// Returns whether it broke through.
+pub fn breach<R: Rng>(map: &mut Map, position: Position, attack: &Attack, rng: &mut R) -> bool {
+    if !attack.breach {
+        return false;
+    }
+    let damage = rng.gen_range(attack.damage.0, attack.damage.1);
+    map.damage_terrain(position.x, position.y, damage)
+}
+
+/// Where something thrown from `origin` towards `target` lands: on
+/// the target, or short of it if it would fly out of range or into a
+/// wall. It stops on the first entity in the way.
+pub fn landing_point(map: &Map, origin: Position, target: Position, range: usize) -> Position {
+    let mut landing = origin;
+    for point in ::util::bresenham(origin, target) {
+        if point == origin {
+            continue;
+        }
+        if ::util::chebyshev((origin.x, origin.y), (point.x, point.y)) > range ||
+            !map.occupable(point.x, point.y) {
+            break;
+        }
+
+        landing = point;
+        if map.contents(point.x, point.y).is_some() {
+            break;
+        }
+    }
+    landing
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{self, Entity};
+
+    use ::components::map::{Map, MapCell};
+    use ::components::player::{AmmoType, Equip, Item, ItemKind, Magazine};
+    use ::components::position::Position;
+    use ::content::Content;
+    use ::util::HasStorage;
+    use ::util::rng::GameRng;
+    use super::{resolve, CombatResult};
+
+    /// A storage with nothing in it.
+    struct Empty;
+
+    impl<C> HasStorage<C> for Empty {
+        fn check(&self, _: Entity) -> bool {
+            false
+        }
+
+        fn get(&self, _: Entity) -> Option<&C> {
+            None
+        }
+    }
+
+    /// The shooter at (10, 10) on an open floor, with a loaded pistol
+    /// and a baton, and someone to shoot at (10, 13).
+    struct Range {
+        me: Entity,
+        equip: Equip,
+        map: Map,
+        rng: GameRng,
+    }
+
+    impl Range {
+        fn new() -> Range {
+            let content = Content::load(concat!(env!("CARGO_MANIFEST_DIR"), "/data/content.toml")).unwrap();
+            let mut map = Map::new(20, 20);
+            for y in 0..20 {
+                for x in 0..20 {
+                    map.set_cell(x, y, MapCell::Floor);
                 }
             }
-            if !map.occupable(target.x, target.y) {
-                return CombatResult::HitEnvironment;
+            let mut world = specs::World::new();
+            let me = world.create_now().build();
+            let them = world.create_now().build();
+            map.fill(me, 10, 10);
+            map.fill(them, 10, 13);
+
+            // Give the baton a magazine too, to see that an attack out
+            // of reach doesn't use it
+            let mut baton = content.items["stun_baton"].clone();
+            if let ItemKind::Weapon { ref mut magazine, .. } = baton.kind {
+                *magazine = Some(Magazine { ammo: AmmoType::Pistol, capacity: 8, loaded: 8 });
+            }
+            let mut equip = Equip::new();
+            equip.primary = Some(content.items["xinhai_pistol"].clone());
+            equip.secondary = Some(baton);
+
+            Range {
+                me: me,
+                equip: equip,
+                map: map,
+                rng: GameRng::new(1),
             }
         }
 
-        return CombatResult::HitNothing;
+        /// Attack `target`, returning the result and how many rounds
+        /// it used.
+        fn attack(&mut self, target: (usize, usize), melee: bool) -> (CombatResult, usize) {
+            let before = self.loaded(melee);
+            let empty = Empty;
+            let result = resolve(&self.map, self.me, &mut self.equip,
+                                 Position::new(10, 10), Position::new(target.0, target.1), melee,
+                                 &empty, &empty, &empty, &empty, &mut self.rng);
+            (result, before - self.loaded(melee))
+        }
+
+        fn loaded(&self, melee: bool) -> usize {
+            let weapon = if melee { &self.equip.secondary } else { &self.equip.primary };
+            match *weapon {
+                Some(Item { kind: ItemKind::Weapon { magazine: Some(magazine), .. }, .. }) => magazine.loaded,
+                _ => 0,
+            }
+        }
     }
-    else {
-        CombatResult::NothingEquipped
+
+    #[test]
+    fn only_shots_taken_use_ammo() {
+        let mut range = Range::new();
+
+        match range.attack((10, 13), false) {
+            (CombatResult::Miss, 1) => {}
+            _ => panic!("a miss should use a round"),
+        }
+        match range.attack((15, 10), false) {
+            (CombatResult::HitNothing, 1) => {}
+            _ => panic!("a shot at nothing should use a round"),
+        }
+        match range.attack((10, 10), false) {
+            (CombatResult::NoTarget, 0) => {}
+            _ => panic!("aiming at yourself shouldn't use a round"),
+        }
+        match range.attack((10, 13), true) {
+            (CombatResult::OutOfRange, 0) => {}
+            _ => panic!("a melee attack out of reach shouldn't use a round"),
+        }
+
+        if let Some(Item { kind: ItemKind::Weapon { magazine: Some(ref mut magazine), .. }, .. }) = range.equip.primary {
+            magazine.loaded = 0;
+        }
+        match range.attack((10, 13), false) {
+            (CombatResult::OutOfAmmo, 0) => {}
+            _ => panic!("an empty weapon shouldn't use a round"),
+        }
     }
 }
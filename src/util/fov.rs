@@ -0,0 +1,98 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ::components::map::Map;
+
+// Transforms from octant-local coordinates to map coordinates
+const MULTIPLIERS: [[i32; 8]; 4] = [
+    [1, 0, 0, -1, -1, 0, 0, 1],
+    [0, 1, -1, 0, 0, -1, 1, 0],
+    [0, 1, 1, 0, 0, -1, -1, 0],
+    [1, 0, 0, 1, -1, 0, 0, -1],
+];
+
+/// Compute the cells visible from `origin` within `radius`, calling
+/// `reveal` for each one (possibly more than once).
+///
+/// Uses recursive shadowcasting; see
+/// http://www.roguebasin.com/index.php?title=FOV_using_recursive_shadowcasting
+pub fn compute<F>(map: &Map, origin: (usize, usize), radius: usize, mut reveal: F)
+    where F: FnMut(usize, usize) {
+    reveal(origin.0, origin.1);
+
+    for octant in 0..8 {
+        cast_light(map, (origin.0 as i32, origin.1 as i32), 1, 1.0, 0.0, radius as i32,
+                   (MULTIPLIERS[0][octant], MULTIPLIERS[1][octant],
+                    MULTIPLIERS[2][octant], MULTIPLIERS[3][octant]),
+                   &mut reveal);
+    }
+}
+
+fn cast_light<F>(map: &Map, center: (i32, i32), row: i32, mut start: f32, end: f32, radius: i32,
+                 (xx, xy, yx, yy): (i32, i32, i32, i32), reveal: &mut F)
+    where F: FnMut(usize, usize) {
+    if start < end {
+        return;
+    }
+
+    let radius2 = radius * radius;
+    let mut new_start = 0.0;
+
+    for j in row..radius + 1 {
+        let dy = -j;
+        let mut blocked = false;
+
+        for dx in -j..1 {
+            let x = center.0 + dx * xx + dy * xy;
+            let y = center.1 + dx * yx + dy * yy;
+            let l_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+            let r_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+
+            if start < r_slope {
+                continue;
+            }
+            else if end > l_slope {
+                break;
+            }
+
+            let in_bounds = x >= 0 && y >= 0 && (x as usize) < map.width && (y as usize) < map.height;
+            if in_bounds && dx * dx + dy * dy <= radius2 {
+                reveal(x as usize, y as usize);
+            }
+
+            let opaque = !in_bounds || map.opaque(x as usize, y as usize);
+            if blocked {
+                if opaque {
+                    new_start = r_slope;
+                }
+                else {
+                    blocked = false;
+                    start = new_start;
+                }
+            }
+            else if opaque && j < radius {
+                // Scan the part of the next row that this cell
+                // doesn't shadow
+                blocked = true;
+                cast_light(map, center, j + 1, start, l_slope, radius, (xx, xy, yx, yy), reveal);
+                new_start = r_slope;
+            }
+        }
+
+        if blocked {
+            break;
+        }
+    }
+}
@@ -18,6 +18,9 @@ use specs::{self, Entity};
 use components::position::Position;
 
 pub mod combat;
+pub mod fov;
+pub mod path;
+pub mod rng;
 
 #[derive(Clone,Copy,Debug,Eq,PartialEq)]
 pub enum Direction {
@@ -25,6 +28,10 @@ pub enum Direction {
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 impl Direction {
@@ -35,14 +42,46 @@ impl Direction {
             Down => (0, 1),
             Left => (-1, 0),
             Right => (1, 0),
+            UpLeft => (-1, -1),
+            UpRight => (1, -1),
+            DownLeft => (-1, 1),
+            DownRight => (1, 1),
         }
     }
+
+    pub fn is_diagonal(&self) -> bool {
+        let (dx, dy) = self.offset();
+        dx != 0 && dy != 0
+    }
+
+    /// The direction of a single step, if it is one.
+    pub fn from_offset(offset: (i32, i32)) -> Option<Direction> {
+        DIRECTIONS.iter().cloned().find(|direction| direction.offset() == offset)
+    }
 }
 
+pub const DIRECTIONS: [Direction; 8] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+    Direction::UpLeft,
+    Direction::UpRight,
+    Direction::DownLeft,
+    Direction::DownRight,
+];
+
 pub fn distance2(a: (usize, usize), b: (usize, usize)) -> usize {
     ((a.0 as i32 - b.0 as i32).pow(2) + (a.1 as i32 - b.1 as i32).pow(2)) as usize
 }
 
+/// The number of steps between two cells when diagonal steps are
+/// allowed.
+pub fn chebyshev(a: (usize, usize), b: (usize, usize)) -> usize {
+    use std::cmp::max;
+    max((a.0 as i32 - b.0 as i32).abs(), (a.1 as i32 - b.1 as i32).abs()) as usize
+}
+
 // Counterclockwise from +x axis
 enum Octant {
     O0,
@@ -91,16 +130,9 @@ impl Octant {
     }
 }
 
+/// The cells on the line from `start` to `end`, in order.
 pub fn bresenham(start: Position, end: Position) -> Vec<Position> {
-    use std::cmp::{min, max};
-
     let mut result = Vec::new();
-    // Vertical line special case
-    if start.x == end.x {
-        for y in min(start.y, end.y)..max(start.y, end.y) + 1 {
-            result.push(Position { x: start.x, y: y });
-        }
-    }
 
     // Figure out the octant
     let horiz_proj = i32::abs(end.x as i32 - start.x as i32);
@@ -220,3 +252,25 @@ impl<'a, C, A, D> HasStorage<C> for &'a specs::Storage<C, A, D>
         specs::Storage::get(self, entity)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::bresenham;
+    use ::components::position::Position;
+
+    fn line(start: (usize, usize), end: (usize, usize)) -> Vec<(usize, usize)> {
+        bresenham(Position::new(start.0, start.1), Position::new(end.0, end.1)).iter()
+            .map(|p| (p.x, p.y))
+            .collect()
+    }
+
+    #[test]
+    fn vertical_down() {
+        assert_eq!(line((5, 2), (5, 5)), vec![(5, 2), (5, 3), (5, 4), (5, 5)]);
+    }
+
+    #[test]
+    fn vertical_up() {
+        assert_eq!(line((5, 5), (5, 2)), vec![(5, 5), (5, 4), (5, 3), (5, 2)]);
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use ::components::map::{DoorState, Map, MapCell};
+use super::{chebyshev, DIRECTIONS};
+
+/// Give up on searches that expand more cells than this.
+const MAX_EXPANDED: usize = 2000;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+struct Node {
+    estimate: usize,
+    cost: usize,
+    index: usize,
+}
+
+// BinaryHeap is a max-heap, so order by lowest estimate first
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other.estimate.cmp(&self.estimate)
+            .then(self.cost.cmp(&other.cost))
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Diagonal steps cost the same as orthogonal ones
+fn heuristic(a: (usize, usize), b: (usize, usize)) -> usize {
+    chebyshev(a, b)
+}
+
+/// The default cost function: walk over empty floor, and into the
+/// goal even if something is standing there (i.e. the target of a
+/// chase). Closed doors cost an extra step to open.
+pub fn walk_cost(map: &Map, goal: (usize, usize), x: usize, y: usize) -> Option<usize> {
+    if map.passable(x, y) || ((x, y) == goal && map.occupable(x, y)) {
+        Some(1)
+    }
+    else if map.cell(x, y) == MapCell::Door(DoorState::Closed) {
+        Some(2)
+    }
+    else {
+        None
+    }
+}
+
+/// Find a path from `start` to `goal` using A*.
+///
+/// `cost` gives the cost of entering a cell, or `None` if the cell
+/// can't be entered. Paths move in eight directions, but never cut
+/// the corner of a wall (see `Map::cuts_corner`). The returned path
+/// does not include `start` but does include `goal`.
+pub fn find_path<F>(map: &Map, start: (usize, usize), goal: (usize, usize), cost: F)
+                    -> Option<Vec<(usize, usize)>>
+    where F: Fn(usize, usize) -> Option<usize> {
+    let start_index = start.1 * map.width + start.0;
+    let goal_index = goal.1 * map.width + goal.0;
+
+    let mut open = BinaryHeap::new();
+    let mut came_from = HashMap::new();
+    let mut best_cost = HashMap::new();
+
+    open.push(Node {
+        estimate: heuristic(start, goal),
+        cost: 0,
+        index: start_index,
+    });
+    best_cost.insert(start_index, 0);
+
+    let mut expanded = 0;
+    while let Some(Node { cost: current_cost, index, .. }) = open.pop() {
+        if index == goal_index {
+            let mut path = vec![goal];
+            let mut current = index;
+            while let Some(&previous) = came_from.get(&current) {
+                if previous == start_index {
+                    break;
+                }
+                path.push((previous % map.width, previous / map.width));
+                current = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        // Skip stale heap entries
+        if best_cost.get(&index).map_or(false, |&c| current_cost > c) {
+            continue;
+        }
+
+        expanded += 1;
+        if expanded > MAX_EXPANDED {
+            return None;
+        }
+
+        let (x, y) = (index % map.width, index / map.width);
+        for direction in DIRECTIONS.iter() {
+            let offset = direction.offset();
+            let nx = x as i32 + offset.0;
+            let ny = y as i32 + offset.1;
+            if nx < 0 || ny < 0 || nx as usize >= map.width || ny as usize >= map.height {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            if map.cuts_corner((x, y), (nx, ny)) {
+                continue;
+            }
+
+            if let Some(step) = cost(nx, ny) {
+                let next_index = ny * map.width + nx;
+                let next_cost = current_cost + step;
+                if best_cost.get(&next_index).map_or(true, |&c| next_cost < c) {
+                    best_cost.insert(next_index, next_cost);
+                    came_from.insert(next_index, index);
+                    open.push(Node {
+                        estimate: next_cost + heuristic((nx, ny), goal),
+                        cost: next_cost,
+                        index: next_index,
+                    });
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Find every cell reachable from `start` for at most `budget`, with
+/// the cheapest cost of reaching each, using Dijkstra's algorithm.
+///
+/// `cost` works as for `find_path`. `start` is included, at no cost.
+pub fn flood<F>(map: &Map, start: (usize, usize), budget: usize, cost: F) -> HashMap<(usize, usize), usize>
+    where F: Fn(usize, usize) -> Option<usize> {
+    let mut open = BinaryHeap::new();
+    let mut best_cost = HashMap::new();
+
+    let start_index = start.1 * map.width + start.0;
+    open.push(Node {
+        estimate: 0,
+        cost: 0,
+        index: start_index,
+    });
+    best_cost.insert(start_index, 0);
+
+    while let Some(Node { cost: current_cost, index, .. }) = open.pop() {
+        // Skip stale heap entries
+        if best_cost.get(&index).map_or(false, |&c| current_cost > c) {
+            continue;
+        }
+
+        let (x, y) = (index % map.width, index / map.width);
+        for direction in DIRECTIONS.iter() {
+            let offset = direction.offset();
+            let nx = x as i32 + offset.0;
+            let ny = y as i32 + offset.1;
+            if nx < 0 || ny < 0 || nx as usize >= map.width || ny as usize >= map.height {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            if map.cuts_corner((x, y), (nx, ny)) {
+                continue;
+            }
+
+            if let Some(step) = cost(nx, ny) {
+                let next_index = ny * map.width + nx;
+                let next_cost = current_cost + step;
+                if next_cost > budget {
+                    continue;
+                }
+                if best_cost.get(&next_index).map_or(true, |&c| next_cost < c) {
+                    best_cost.insert(next_index, next_cost);
+                    open.push(Node {
+                        estimate: next_cost,
+                        cost: next_cost,
+                        index: next_index,
+                    });
+                }
+            }
+        }
+    }
+
+    best_cost.into_iter()
+        .map(|(index, cost)| ((index % map.width, index / map.width), cost))
+        .collect()
+}
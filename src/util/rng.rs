@@ -0,0 +1,86 @@
+// Copyright (C) 2016-2017 David Li
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use rand::Rng;
+
+const MAPGEN_STREAM: u64 = 0x6d61_7067_656e;
+const COMBAT_STREAM: u64 = 0x636f_6d62_6174;
+const LOOT_STREAM: u64 = 0x6c6f_6f74;
+const AI_STREAM: u64 = 0x6169;
+
+/// A xorshift64* generator. Unlike the generators in `rand`, its
+/// state is plain data, so a run can be reproduced from its seed.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Serialize,Deserialize)]
+pub struct GameRng {
+    pub state: u64,
+}
+
+/// The random number streams used by the game, all derived from a
+/// single seed. Each kind of decision draws from its own stream, so
+/// that (for instance) an extra combat roll doesn't change the loot
+/// that drops later.
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct Rngs {
+    pub seed: u64,
+    pub mapgen: GameRng,
+    pub combat: GameRng,
+    pub loot: GameRng,
+    pub ai: GameRng,
+}
+
+/// Scramble a seed so that similar seeds give unrelated streams.
+fn splitmix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        let state = splitmix(seed);
+        GameRng {
+            // xorshift never leaves the zero state
+            state: if state == 0 { 1 } else { state },
+        }
+    }
+}
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+impl Rngs {
+    pub fn new(seed: u64) -> Rngs {
+        Rngs {
+            seed: seed,
+            mapgen: GameRng::new(seed ^ MAPGEN_STREAM),
+            combat: GameRng::new(seed ^ COMBAT_STREAM),
+            loot: GameRng::new(seed ^ LOOT_STREAM),
+            ai: GameRng::new(seed ^ AI_STREAM),
+        }
+    }
+}